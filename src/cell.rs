@@ -1,19 +1,116 @@
+use crate::coupled_system::Fields;
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
+use crate::grid::lattice::Lattice;
+use crate::grid::Grid;
+use crate::grid3d::GridView3;
+use crate::image::ImagePixel;
 
 use crate::util::{Color, RandomGenerator};
+use num_complex::Complex32;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use winit::event::VirtualKeyCode;
 
 // TODO: deprecate this oopy shit
-pub trait Cell: Clone {
+// Send + Sync so that a grid of cells can be updated from several threads,
+// and serializable so that it can be saved in a snapshot.
+pub trait Cell: Clone + Send + Sync + Serialize + DeserializeOwned {
+    /// Physical parameters shared by every cell of a grid, read through `GridView::params`.
+    type Params: Clone + Default + Send + Sync;
     fn update(&self, grid_view: GridView<Self>) -> Self;
+    /// Fill `next` with the next state of every cell of `grid`, for rules which are
+    /// cheaper to compute for the whole grid at once. Returns `false` to use `update`.
+    fn update_grid(_grid: &Grid<Self>, _params: &Self::Params, _next: &mut [Self]) -> bool {
+        false
+    }
+    /// Random kicks to every cell after each update, e.g. thermal noise. Returns
+    /// `false` if no cell was changed.
+    fn thermalize(_cells: &mut [Self], _params: &Self::Params) -> bool {
+        false
+    }
     // todo make toggle and line action return new cells
     fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos);
     fn line_action(&mut self, target_pos: &GridPos, grid_pos: &GridPos, alive: bool);
+    /// For two state cells, whether this one is alive, e.g. to export Life patterns.
+    fn is_alive(&self) -> Option<bool> {
+        None
+    }
+    /// Fills in the params which depend on the grid's `lattice`, e.g. a neighbor count.
+    fn on_lattice(params: Self::Params, _lattice: Lattice) -> Result<Self::Params, String> {
+        Ok(params)
+    }
+    /// The rule being run, for the header of exported Life patterns.
+    fn rulestring(_params: &Self::Params) -> Option<String> {
+        None
+    }
+    /// Names of the numeric fields of the cell, e.g. to export raw data with `field_value`.
+    fn field_names() -> &'static [&'static str] {
+        &[]
+    }
+    /// The value of the field at `index` in `field_names`.
+    fn field_value(&self, _index: usize) -> f32 {
+        0.0
+    }
+    /// Measurements of a whole grid, e.g. the total energy, as (name, value) columns.
+    fn observables(_grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        vec![]
+    }
+    /// Extra keys handled in the window, e.g. to print a measurement.
+    fn key_response(_grid: &Grid<Self>, _params: &Self::Params, _vkc: VirtualKeyCode) {}
     // fn aggregate(&self) ->
 }
 
-pub trait Randomize {
-    fn random(rng: &mut RandomGenerator, grid_pos: GridPos) -> Self;
+/// Cells which can also be updated on a 3D grid.
+pub trait Cell3: Cell {
+    fn update_3d(&self, grid_view: GridView3<Self>) -> Self;
+    /// Rejects params which only make sense on 2D grids.
+    fn check_3d(_params: &Self::Params) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Cells of one field of a `CoupledSystem`, which can read the other fields.
+pub trait CoupledCell: Cell {
+    fn update_coupled(&self, grid_view: GridView<Self>, fields: &Fields) -> Self;
+}
+
+pub trait Randomize: Cell {
+    /// A random cell, e.g. alive with the probability set in `params`.
+    fn random(rng: &mut RandomGenerator, grid_pos: GridPos, params: &Self::Params) -> Self;
+    /// An empty cell. Cells with their own random state seed it from `rng`, as in `random`.
+    fn empty(_rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self
+    where
+        Self: Default,
+    {
+        Self::default()
+    }
+}
+
+/// Cells which can be built from a single number, e.g. to hold a boundary at a fixed value.
+pub trait BoundaryValue: Sized {
+    fn from_value(_value: f32) -> Option<Self> {
+        None
+    }
+    /// The cell with the sign of its field flipped, e.g. for an antiperiodic boundary.
+    fn negate(&self) -> Option<Self> {
+        None
+    }
+}
+
+/// Cells which a scene can add an amplitude to, e.g. a wave packet or the angle of a vortex.
+/// Real valued cells only take the real part.
+pub trait Superpose: Sized {
+    fn superpose(&self, _amplitude: Complex32) -> Option<Self> {
+        None
+    }
+}
+
+/// Cells which an image can be mapped into, e.g. brightness to density or hue to angle.
+pub trait FromPixel: Cell {
+    fn from_pixel(_pixel: &ImagePixel, _params: &Self::Params) -> Option<Self> {
+        None
+    }
 }
 
 pub trait HasColor {
@@ -1,7 +1,10 @@
 mod complex_diffusion;
 mod conway_cell;
 mod diffusion;
+mod generations;
+mod larger_than_life;
 mod particle_diffusion;
+mod predator_prey;
 mod sandpile;
 mod schrodinger;
 mod wave_equation;
@@ -10,11 +13,18 @@ mod xy_model;
 // mod phased_particle_diffusion;
 
 pub use complex_diffusion::ComplexDiffusionCell;
-pub use conway_cell::ConwayCell;
+pub use conway_cell::{ConwayCell, LifeRule};
 pub use diffusion::DiffusionCell;
+pub use generations::GenerationsCell;
+pub use larger_than_life::LargerThanLifeCell;
 pub use particle_diffusion::ParticleDiffusionCell;
+pub use predator_prey::{PredatorCell, PreyCell, PREDATOR_FIELD, PREY_FIELD};
 // pub use phased_particle_diffusion::PhasedParticleDiffusionCell;
 pub use sandpile::AbelianSandpileCell;
+#[cfg(test)]
+pub use sandpile::SandpileParams;
 pub use schrodinger::SchrodingerCell;
 pub use wave_equation::WaveCell;
+#[cfg(test)]
+pub use wave_equation::WaveParams;
 pub use xy_model::XYModelCell;
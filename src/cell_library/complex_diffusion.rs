@@ -1,56 +1,88 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{
-    complex_to_hue, gauss, stencil_coords, Color, RandomGenerator, NEAREST_NEIGHBORS,
-    N_NEAREST_NEIGHBORS, SECOND_ORDER_CENTRAL,
-};
-use num_complex::Complex64;
+use crate::grid::neighborhood::NeighborhoodParam;
+use crate::grid::Grid;
+use crate::integrator::FieldCell;
+use crate::util::{complex_to_hue, gauss, next_u64, Color, RandomGenerator};
+use num_complex::{Complex32, Complex64};
 use palette::{Hsv, LinSrgb, Pixel};
+use serde::{Deserialize, Serialize};
 
 type Density = Complex64;
 type Float = f64;
 const MAX_ABS: Float = 1.0;
 
-#[derive(Default, Clone)]
+/// The `[complex_diffusion]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComplexDiffusionParams {
+    /// Imaginary part of the diffusion constant, making this a free Schrodinger equation
+    pub diffusion_constant: Float,
+    /// The cells exchanging density with each cell, as in `[diffusion]`
+    pub neighborhood: NeighborhoodParam,
+}
+impl Default for ComplexDiffusionParams {
+    fn default() -> Self {
+        Self {
+            diffusion_constant: 0.005,
+            neighborhood: NeighborhoodParam::default(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct ComplexDiffusionCell {
     pub(crate) density: Density,
 }
 impl ComplexDiffusionCell {
-    fn diffusion_constant() -> Density {
-        0.005 * Density::i()
-    }
-    fn avg_neighbors(grid_view: GridView<Self>) -> Density {
-        NEAREST_NEIGHBORS
-            .iter()
-            .map(|dxy| grid_view.get_cell_at_coord(dxy[0], dxy[1]).density)
-            .sum::<Density>()
-            / (N_NEAREST_NEIGHBORS as Float)
-    }
-    fn laplace(grid_view: GridView<Self>) -> Density {
-        SECOND_ORDER_CENTRAL
-            .iter()
-            .flatten()
-            .zip(stencil_coords(3, 3))
-            .map(|(&weight, dpos)| (weight as Float) * grid_view.get_cell_at(dpos).density)
-            .sum()
+    fn diffusion_constant(params: &ComplexDiffusionParams) -> Density {
+        params.diffusion_constant * Density::i()
+    }
+    fn laplace(&self, grid_view: &GridView<Self>) -> Density {
+        match grid_view.params().neighborhood.get() {
+            Some(neighborhood) => grid_view
+                .weighted_neighbors(neighborhood)
+                .map(|(weight, cell)| (weight as Float) * (cell.density - self.density))
+                .sum(),
+            None => grid_view
+                .laplace_neighbors()
+                .map(|(weight, cell)| (weight as Float) * cell.density)
+                .sum(),
+        }
+    }
+}
+impl BoundaryValue for ComplexDiffusionCell {
+    fn from_value(value: f32) -> Option<Self> {
+        Some(Self {
+            density: Density::new(value as Float, 0.0),
+        })
+    }
+    fn negate(&self) -> Option<Self> {
+        Some(Self {
+            density: -self.density,
+        })
+    }
+}
+impl Superpose for ComplexDiffusionCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        let amplitude = Density::new(amplitude.re as Float, amplitude.im as Float);
+        Some(Self {
+            density: self.density + amplitude,
+        })
     }
 }
+impl FromPixel for ComplexDiffusionCell {}
 impl Randomize for ComplexDiffusionCell {
-    fn random(_rng: &mut RandomGenerator, grid_pos: GridPos) -> Self {
-        let _radius = if (grid_pos.x > 50) & (grid_pos.y > 50) {
-            //randomize::f32_half_open_right(rng.next_u32()) * 1.0
-            1.0
-        } else {
-            0.0
-        };
-        let radius = Float::sin(2.0 * std::f64::consts::PI * (grid_pos.x as Float) / 100.0);
-        let radius =
-            radius * Float::sin(2.0 * std::f64::consts::PI * (grid_pos.y as Float) / 100.0);
-        //let theta = randomize::f32_half_open_right(rng.next_u32()) * 2.0 * PI;
-        let theta = 0.0;
+    fn random(
+        rng: &mut RandomGenerator,
+        _grid_pos: GridPos,
+        _params: &ComplexDiffusionParams,
+    ) -> Self {
+        let radius = randomize::f64_half_open_right(next_u64(rng)) * MAX_ABS;
+        let theta = randomize::f64_half_open_right(next_u64(rng)) * std::f64::consts::TAU;
         Self {
-            density: Density::from_polar(radius as Float, theta as Float),
+            density: Density::from_polar(radius, theta),
         }
     }
 }
@@ -65,12 +97,9 @@ impl HasColor for ComplexDiffusionCell {
     }
 }
 impl Cell for ComplexDiffusionCell {
+    type Params = ComplexDiffusionParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        let new_density: Density =
-            Self::laplace(grid_view) * Self::diffusion_constant() + self.density;
-        Self {
-            density: new_density,
-        }
+        self.semi_implicit_euler(&grid_view)
     }
 
     fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
@@ -79,6 +108,34 @@ impl Cell for ComplexDiffusionCell {
     }
 
     fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let norm = grid.cells.iter().map(|cell| cell.density.norm_sqr()).sum();
+        vec![("norm", norm)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["real", "imag"]
+    }
+    fn field_value(&self, index: usize) -> f32 {
+        match index {
+            0 => self.density.re as f32,
+            _ => self.density.im as f32,
+        }
+    }
+}
+impl FieldCell for ComplexDiffusionCell {
+    type State = Density;
+    fn dt(_params: &ComplexDiffusionParams) -> f32 {
+        1.0
+    }
+    fn state(&self) -> Density {
+        self.density
+    }
+    fn with_state(&self, density: Density) -> Self {
+        Self { density }
+    }
+    fn derivative(&self, grid_view: &GridView<Self>) -> Density {
+        self.laplace(grid_view) * Self::diffusion_constant(grid_view.params())
+    }
 }
 
 #[test]
@@ -1,15 +1,120 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::NEAREST_NEIGHBORS;
+use crate::grid::neighborhood::NeighborhoodParam;
+use crate::grid::Grid;
+use crate::image::ImagePixel;
 use crate::util::{Color, RandomGenerator};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
-const BIRTH_RULE: [bool; 9] = [false, false, false, true, false, false, false, false, false];
-const SURVIVE_RULE: [bool; 9] = [false, false, true, true, false, false, false, false, false];
+/// An outer totalistic rule on the Moore neighborhood: the neighbor counts at
+/// which dead cells are born and live cells survive. Written as a rulestring,
+/// e.g. `B36/S23` for HighLife, or in the older S/B notation as `23/36`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LifeRule {
+    pub birth: [bool; 9],
+    pub survive: [bool; 9],
+}
+impl LifeRule {
+    /// Whether a cell with `n` live neighbors is alive next step. Triangular
+    /// lattices have up to 12 neighbors; counts beyond the rule are dead.
+    pub fn next_alive(&self, alive: bool, n: usize) -> bool {
+        let counts = if alive { &self.survive } else { &self.birth };
+        counts.get(n).copied().unwrap_or(false)
+    }
+}
+impl Default for LifeRule {
+    fn default() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+}
+impl FromStr for LifeRule {
+    type Err = String;
+    fn from_str(rule: &str) -> Result<Self, String> {
+        let error = |why: &str| format!("invalid rule `{}`: {}", rule, why);
+        let counts = |digits: &str| {
+            let mut counts = [false; 9];
+            for c in digits.chars() {
+                match c.to_digit(10) {
+                    Some(n) if n < 9 => counts[n as usize] = true,
+                    _ => return Err(error(&format!("`{}` is not a neighbor count", c))),
+                }
+            }
+            Ok(counts)
+        };
+        let compact: String = rule.split_whitespace().collect();
+        let (first, second) = compact
+            .split_once('/')
+            .ok_or_else(|| error("expected e.g. B3/S23"))?;
+        let is = |part: &str, letter: char| part.starts_with([letter, letter.to_ascii_lowercase()]);
+        let (birth, survive) = if is(first, 'B') && is(second, 'S') {
+            (&first[1..], &second[1..])
+        } else if is(first, 'S') && is(second, 'B') {
+            (&second[1..], &first[1..])
+        } else if !first.starts_with(char::is_alphabetic)
+            && !second.starts_with(char::is_alphabetic)
+        {
+            // S/B notation, e.g. `23/3`
+            (second, first)
+        } else {
+            return Err(error("expected a B part and an S part"));
+        };
+        Ok(Self {
+            birth: counts(birth)?,
+            survive: counts(survive)?,
+        })
+    }
+}
+impl fmt::Display for LifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = |counts: &[bool; 9]| -> String {
+            (0..9)
+                .filter(|&n| counts[n])
+                .map(|n| n.to_string())
+                .collect()
+        };
+        write!(f, "B{}/S{}", digits(&self.birth), digits(&self.survive))
+    }
+}
+impl TryFrom<String> for LifeRule {
+    type Error = String;
+    fn try_from(rule: String) -> Result<Self, String> {
+        rule.parse()
+    }
+}
+impl From<LifeRule> for String {
+    fn from(rule: LifeRule) -> Self {
+        rule.to_string()
+    }
+}
 
-const INITIAL_FILL: f32 = 0.3;
+/// The `[conway]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConwayParams {
+    /// e.g. `B36/S23` for HighLife, `B3678/S34678` for Day & Night
+    pub rule: LifeRule,
+    /// Fraction of live cells in a random grid
+    pub initial_fill: f32,
+    /// Where to count live neighbors, e.g. `{ moore = 2 }`. Stencil weights are
+    /// ignored, and counts past 8 are dead.
+    pub neighborhood: NeighborhoodParam,
+}
+impl Default for ConwayParams {
+    fn default() -> Self {
+        Self {
+            rule: LifeRule::default(),
+            initial_fill: 0.7,
+            neighborhood: NeighborhoodParam::default(),
+        }
+    }
+}
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct ConwayCell {
     pub alive: bool,
     // Used for the trail effect. Always 255 if `self.alive` is true (We could
@@ -24,10 +129,16 @@ impl ConwayCell {
         Self { alive, heat: 0 }
     }
     fn count_neibs(grid_view: GridView<Self>) -> usize {
-        NEAREST_NEIGHBORS
-            .iter()
-            .map(|dxy| grid_view.get_cell_at_coord(dxy[0], dxy[1]).alive as usize)
-            .sum()
+        match grid_view.params().neighborhood.get() {
+            Some(neighborhood) => grid_view
+                .neighbors(neighborhood)
+                .filter(|cell| cell.alive)
+                .count(),
+            None => grid_view
+                .vertex_neighbors()
+                .filter(|cell| cell.alive)
+                .count(),
+        }
     }
     fn set_alive(&mut self, alive: bool) {
         *self = self.next_state(alive);
@@ -43,9 +154,20 @@ impl ConwayCell {
         self
     }
 }
+impl BoundaryValue for ConwayCell {
+    fn from_value(value: f32) -> Option<Self> {
+        Some(Self::new(value != 0.0))
+    }
+}
+impl Superpose for ConwayCell {}
+impl FromPixel for ConwayCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &ConwayParams) -> Option<Self> {
+        Some(Self::new(pixel.is_bright()))
+    }
+}
 impl Randomize for ConwayCell {
-    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self {
-        let alive = randomize::f32_half_open_right(rng.next_u32()) > INITIAL_FILL;
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, params: &ConwayParams) -> Self {
+        let alive = randomize::f32_half_open_right(rng.next_u32()) < params.initial_fill;
         ConwayCell::new(alive)
     }
 }
@@ -59,14 +181,11 @@ impl HasColor for ConwayCell {
     }
 }
 impl Cell for ConwayCell {
+    type Params = ConwayParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
+        let rule = grid_view.params().rule;
         let n = ConwayCell::count_neibs(grid_view);
-        let next_alive = if self.alive {
-            SURVIVE_RULE[n]
-        } else {
-            BIRTH_RULE[n]
-        };
-        self.next_state(next_alive)
+        self.next_state(rule.next_alive(self.alive, n))
     }
 
     fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
@@ -79,4 +198,59 @@ impl Cell for ConwayCell {
     fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, alive: bool) {
         self.set_alive(alive);
     }
+    fn is_alive(&self) -> Option<bool> {
+        Some(self.alive)
+    }
+    fn rulestring(params: &ConwayParams) -> Option<String> {
+        Some(params.rule.to_string())
+    }
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let population = grid.cells.iter().map(|cell| cell.alive as u8 as f64).sum();
+        vec![("population", population)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["alive"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.alive as u8 as f32
+    }
+}
+
+#[test]
+fn test_life_rules() {
+    let rule = |text: &str| text.parse::<LifeRule>();
+    assert_eq!(rule("B3/S23"), Ok(LifeRule::default()));
+    assert_eq!(rule("23/3"), Ok(LifeRule::default()));
+    assert_eq!(rule("s23/b3"), Ok(LifeRule::default()));
+    let day_and_night = rule("B3678/S34678").unwrap();
+    assert_eq!(day_and_night.to_string(), "B3678/S34678");
+    assert!(day_and_night.next_alive(false, 6) && !day_and_night.next_alive(true, 5));
+    // Seeds never survives
+    assert_eq!(rule("B2/S").unwrap().to_string(), "B2/S");
+    for bad in ["B3", "B9/S23", "B3/S2x", "B3/B3", "X3/S23", "B3/S23/C2"] {
+        assert!(rule(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_neighborhood_param() {
+    use crate::grid::boundary::PeriodicBoundary;
+
+    // Three live cells two steps away only count with a larger neighborhood
+    let mut grid = Grid::<ConwayCell>::new_empty(7, 7, PeriodicBoundary.into());
+    for (x, y) in [(1, 3), (5, 3), (3, 1)] {
+        let idx = grid.to_idx(&GridPos::new(x, y));
+        grid.cells[idx].alive = true;
+    }
+    let born = |neighborhood: &str| {
+        let params: ConwayParams =
+            toml::from_str(&format!("neighborhood = {}", neighborhood)).unwrap();
+        let center = GridPos::new(3, 3);
+        let view = GridView::new(center, &grid, &params);
+        grid.get_cell_at(center).update(view).alive
+    };
+    assert!(!born("\"lattice\""));
+    assert!(born("{ moore = 2 }"));
+    assert!(born("{ circular = 2.0 }"));
+    assert!(!born("{ von_neumann = 1 }"));
 }
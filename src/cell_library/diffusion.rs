@@ -1,44 +1,84 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, Cell3, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{
-    gauss, map_to_unit_interval, stencil_coords, Color, RandomGenerator, NEAREST_NEIGHBORS,
-    N_NEAREST_NEIGHBORS, SECOND_ORDER_CENTRAL,
-};
+use crate::grid::neighborhood::NeighborhoodParam;
+use crate::grid::Grid;
+use crate::grid3d::GridView3;
+use crate::image::ImagePixel;
+use crate::integrator::FieldCell;
+use crate::util::{gauss, map_to_unit_interval, Color, RandomGenerator};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
 
 type Density = f32;
 const MIN_VISIBLE_DENSITY: Density = 0.0;
 const MAX_VISIBLE_DENSITY: Density = 1.0;
-const DIFFUSION_CONSTANT: Density = 0.01;
 
-#[derive(Clone, Default)]
+/// The `[diffusion]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffusionParams {
+    pub diffusion_constant: Density,
+    /// The cells exchanging density with each cell, at the diffusion constant
+    /// times their weight. The lattice's own is its laplacian. 2D grids only.
+    pub neighborhood: NeighborhoodParam,
+}
+impl Default for DiffusionParams {
+    fn default() -> Self {
+        Self {
+            diffusion_constant: 0.01,
+            neighborhood: NeighborhoodParam::default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct DiffusionCell {
     pub density: Density,
 }
 impl DiffusionCell {
-    fn avg_neighbors(grid_view: GridView<Self>) -> Density {
-        NEAREST_NEIGHBORS
-            .iter()
-            .map(|dxy| grid_view.get_cell_at_coord(dxy[0], dxy[1]).density)
-            .sum::<Density>()
-            / (N_NEAREST_NEIGHBORS as Density)
-    }
-    fn laplace(grid_view: GridView<Self>) -> Density {
-        SECOND_ORDER_CENTRAL
-            .iter()
-            .flatten()
-            .zip(stencil_coords(3, 3))
-            .map(|(weight, dpos)| weight * grid_view.get_cell_at(dpos).density)
-            .sum()
+    fn laplace(&self, grid_view: &GridView<Self>) -> Density {
+        match grid_view.params().neighborhood.get() {
+            Some(neighborhood) => grid_view
+                .weighted_neighbors(neighborhood)
+                .map(|(weight, cell)| weight * (cell.density - self.density))
+                .sum(),
+            None => grid_view
+                .laplace_neighbors()
+                .map(|(weight, cell)| weight * cell.density)
+                .sum(),
+        }
+    }
+    fn step(&self, laplace: Density, params: &DiffusionParams) -> Self {
+        Self {
+            density: laplace * params.diffusion_constant + self.density,
+        }
+    }
+}
+impl BoundaryValue for DiffusionCell {
+    fn from_value(density: Density) -> Option<Self> {
+        Some(Self { density })
+    }
+}
+impl Superpose for DiffusionCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            density: self.density + amplitude.re,
+        })
+    }
+}
+impl FromPixel for DiffusionCell {
+    // The inverse of `draw`
+    fn from_pixel(pixel: &ImagePixel, _params: &DiffusionParams) -> Option<Self> {
+        let range = MAX_VISIBLE_DENSITY - MIN_VISIBLE_DENSITY;
+        Some(Self {
+            density: MIN_VISIBLE_DENSITY + range * pixel.brightness(),
+        })
     }
 }
 impl Randomize for DiffusionCell {
-    fn random(rng: &mut RandomGenerator, grid_pos: GridPos) -> Self {
-        let density = if grid_pos.x > 50 {
-            randomize::f32_half_open_right(rng.next_u32()) * 1.0
-        } else {
-            0.0
-        };
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &DiffusionParams) -> Self {
+        let density = randomize::f32_half_open_right(rng.next_u32());
         Self { density }
     }
 }
@@ -53,11 +93,9 @@ impl HasColor for DiffusionCell {
     }
 }
 impl Cell for DiffusionCell {
+    type Params = DiffusionParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        let new_density: Density = Self::laplace(grid_view) * DIFFUSION_CONSTANT + self.density;
-        Self {
-            density: new_density,
-        }
+        self.semi_implicit_euler(&grid_view)
     }
 
     fn toggle(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos) {}
@@ -65,4 +103,72 @@ impl Cell for DiffusionCell {
     fn line_action(&mut self, target_pos: &GridPos, grid_pos: &GridPos, _alive: bool) {
         self.density += gauss(1.0, [20.0, 20.0], &target_pos, &grid_pos)
     }
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let mass = grid.cells.iter().map(|cell| cell.density as f64).sum();
+        vec![("mass", mass)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["density"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.density
+    }
+}
+impl FieldCell for DiffusionCell {
+    type State = Density;
+    fn dt(_params: &DiffusionParams) -> Density {
+        1.0
+    }
+    fn state(&self) -> Density {
+        self.density
+    }
+    fn with_state(&self, density: Density) -> Self {
+        Self { density }
+    }
+    fn derivative(&self, grid_view: &GridView<Self>) -> Density {
+        self.laplace(grid_view) * grid_view.params().diffusion_constant
+    }
+}
+impl Cell3 for DiffusionCell {
+    fn check_3d(params: &DiffusionParams) -> Result<(), String> {
+        match params.neighborhood.get() {
+            Some(_) => Err("[diffusion]: only the lattice's neighborhood is 3D".to_string()),
+            None => Ok(()),
+        }
+    }
+    fn update_3d(&self, grid_view: GridView3<Self>) -> Self {
+        let laplace = grid_view
+            .laplace_neighbors()
+            .map(|(weight, cell)| weight * cell.density)
+            .sum();
+        self.step(laplace, grid_view.params())
+    }
+}
+
+#[test]
+fn test_neighborhood_param() {
+    use crate::grid::boundary::PeriodicBoundary;
+
+    let mut grid = Grid::<DiffusionCell>::new_empty(9, 7, PeriodicBoundary.into());
+    grid.randomize(&DiffusionParams::default());
+    let derivatives = |neighborhood: &str| {
+        let params: DiffusionParams =
+            toml::from_str(&format!("neighborhood = {}", neighborhood)).unwrap();
+        grid.get_grid_pos_iter()
+            .map(|grid_pos| {
+                let view = GridView::new(grid_pos, &grid, &params);
+                grid.get_cell_at(grid_pos).derivative(&view)
+            })
+            .collect::<Vec<_>>()
+    };
+    // The nearest neighbors give the 5 point laplacian, and any neighborhood keeps the mass
+    let lattice = derivatives("\"lattice\"");
+    for (a, b) in lattice.iter().zip(derivatives("{ von_neumann = 1 }")) {
+        assert!((a - b).abs() < 1e-6);
+    }
+    let mass_change: Density = derivatives("{ circular = 2.5 }").iter().sum();
+    assert!(mass_change.abs() < 1e-5);
+
+    let params: DiffusionParams = toml::from_str("neighborhood = { moore = 2 }").unwrap();
+    assert!(DiffusionCell::check_3d(&params).is_err());
 }
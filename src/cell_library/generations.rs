@@ -0,0 +1,229 @@
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
+use crate::cell_library::LifeRule;
+use crate::grid::grid_pos::GridPos;
+use crate::grid::grid_view::GridView;
+use crate::grid::neighborhood::NeighborhoodParam;
+use crate::grid::Grid;
+use crate::image::ImagePixel;
+use crate::util::{Color, RandomGenerator};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Life-like rule where dying cells take `states - 2` more steps to become
+/// dead, and can't be born again meanwhile. Written as `B2/S/C3`, or in the
+/// S/B/C notation as `/2/3` (Brian's Brain) or `345/2/4` (Star Wars).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct GenerationsRule {
+    pub life: LifeRule,
+    /// Number of states, counting dead and alive
+    pub states: u8,
+}
+impl Default for GenerationsRule {
+    fn default() -> Self {
+        "B2/S/C3".parse().unwrap()
+    }
+}
+impl FromStr for GenerationsRule {
+    type Err = String;
+    fn from_str(rule: &str) -> Result<Self, String> {
+        let error = |why: &str| format!("invalid rule `{}`: {}", rule, why);
+        let (life, states) = rule
+            .rsplit_once('/')
+            .ok_or_else(|| error("expected e.g. B2/S/C3"))?;
+        let states = states.trim();
+        let states = states
+            .strip_prefix(['C', 'c'])
+            .unwrap_or(states)
+            .parse()
+            .ok()
+            .filter(|&states| states >= 2)
+            .ok_or_else(|| error("the number of states should be from 2 to 255"))?;
+        Ok(Self {
+            life: life.parse()?,
+            states,
+        })
+    }
+}
+impl fmt::Display for GenerationsRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/C{}", self.life, self.states)
+    }
+}
+impl TryFrom<String> for GenerationsRule {
+    type Error = String;
+    fn try_from(rule: String) -> Result<Self, String> {
+        rule.parse()
+    }
+}
+impl From<GenerationsRule> for String {
+    fn from(rule: GenerationsRule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// The `[generations]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationsParams {
+    /// e.g. `B2/S/C3` for Brian's Brain, `B2/S345/C4` for Star Wars
+    pub rule: GenerationsRule,
+    /// Fraction of live cells in a random grid, the rest are dead
+    pub initial_fill: f32,
+    /// Where to count live neighbors, as in `[conway]`
+    pub neighborhood: NeighborhoodParam,
+}
+impl Default for GenerationsParams {
+    fn default() -> Self {
+        Self {
+            rule: GenerationsRule::default(),
+            initial_fill: 0.7,
+            neighborhood: NeighborhoodParam::default(),
+        }
+    }
+}
+
+/// State 0 is dead, 1 is alive, and the ones after that are dying.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct GenerationsCell {
+    pub state: u8,
+}
+impl GenerationsCell {
+    const DEAD: u8 = 0;
+    const ALIVE: u8 = 1;
+
+    fn count_alive_neibs(grid_view: &GridView<Self>) -> usize {
+        let alive = |cell: &&Self| cell.state == Self::ALIVE;
+        match grid_view.params().neighborhood.get() {
+            Some(neighborhood) => grid_view.neighbors(neighborhood).filter(alive).count(),
+            None => grid_view.vertex_neighbors().filter(alive).count(),
+        }
+    }
+}
+impl BoundaryValue for GenerationsCell {
+    fn from_value(value: f32) -> Option<Self> {
+        Some(Self {
+            state: value.max(0.0) as u8,
+        })
+    }
+}
+impl Superpose for GenerationsCell {}
+impl FromPixel for GenerationsCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &GenerationsParams) -> Option<Self> {
+        Some(Self {
+            state: pixel.is_bright() as u8,
+        })
+    }
+}
+impl Randomize for GenerationsCell {
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, params: &GenerationsParams) -> Self {
+        let alive = randomize::f32_half_open_right(rng.next_u32()) < params.initial_fill;
+        Self { state: alive as u8 }
+    }
+}
+impl HasColor for GenerationsCell {
+    fn draw(&self) -> Color {
+        match self.state {
+            Self::DEAD => [0, 0, 0, 0xff],
+            Self::ALIVE => [0, 0xff, 0xff, 0xff],
+            // Fading from orange to dark red, so that each dying state stands out
+            dying => {
+                let fade = 0.75f32.powi(dying as i32 - 2);
+                [
+                    (0xff as f32 * fade.sqrt()) as u8,
+                    (0xa0 as f32 * fade * fade) as u8,
+                    0x20,
+                    0xff,
+                ]
+            }
+        }
+    }
+}
+impl Cell for GenerationsCell {
+    type Params = GenerationsParams;
+    fn update(&self, grid_view: GridView<Self>) -> Self {
+        let rule = grid_view.params().rule;
+        let state = match self.state {
+            Self::DEAD | Self::ALIVE => {
+                let alive = self.state == Self::ALIVE;
+                let n = Self::count_alive_neibs(&grid_view);
+                match (alive, rule.life.next_alive(alive, n)) {
+                    (_, true) => Self::ALIVE,
+                    (false, false) => Self::DEAD,
+                    (true, false) => 2,
+                }
+            }
+            dying => dying.saturating_add(1),
+        };
+        // Past the last dying state, or right away with only two states
+        let state = if state < rule.states {
+            state
+        } else {
+            Self::DEAD
+        };
+        Self { state }
+    }
+
+    fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
+        if (target_pos.x == grid_pos.x) & (target_pos.y == grid_pos.y) {
+            self.state = (self.state != Self::ALIVE) as u8;
+        }
+    }
+
+    fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, alive: bool) {
+        self.state = alive as u8;
+    }
+    fn is_alive(&self) -> Option<bool> {
+        Some(self.state == Self::ALIVE)
+    }
+    fn rulestring(params: &GenerationsParams) -> Option<String> {
+        Some(params.rule.to_string())
+    }
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let count = |states: &dyn Fn(u8) -> bool| {
+            grid.cells.iter().filter(|cell| states(cell.state)).count() as f64
+        };
+        vec![
+            ("population", count(&|state| state == Self::ALIVE)),
+            ("dying", count(&|state| state > Self::ALIVE)),
+        ]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["state"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.state as f32
+    }
+}
+
+#[test]
+fn test_generations() {
+    use crate::grid::boundary::PeriodicBoundary;
+
+    let rule = |text: &str| text.parse::<GenerationsRule>().map(|rule| rule.to_string());
+    assert_eq!(rule("/2/3"), Ok("B2/S/C3".to_string()));
+    assert_eq!(rule("345/2/4"), Ok("B2/S345/C4".to_string()));
+    assert_eq!(rule("B2/S345/C4"), Ok("B2/S345/C4".to_string()));
+    assert!(rule("B2/S").is_err());
+    assert!(rule("B2/S/C1").is_err());
+
+    // In Brian's Brain, live cells always start dying, and dying cells can't
+    // be born even with two live neighbors. States past the last one, e.g. from
+    // a `Fill` layer, die without overflowing.
+    let mut grid = Grid::<GenerationsCell>::new_empty(5, 5, PeriodicBoundary.into());
+    for (x, y, state) in [(1, 1, 1), (1, 3, 1), (2, 2, 2), (4, 4, 255)] {
+        let idx = grid.to_idx(&GridPos::new(x, y));
+        grid.cells[idx].state = state;
+    }
+    let params = GenerationsParams::default();
+    let next = |x, y| {
+        let view = GridView::new(GridPos::new(x, y), &grid, &params);
+        grid.get_cell_at(GridPos::new(x, y)).update(view).state
+    };
+    assert_eq!(
+        (next(1, 1), next(2, 2), next(0, 2), next(4, 4)),
+        (2, 0, 1, 0)
+    );
+}
@@ -0,0 +1,353 @@
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::grid_view::GridView;
+use crate::grid::neighborhood::Neighborhood;
+use crate::grid::summed_area::SummedAreaTable;
+use crate::grid::Grid;
+use crate::image::ImagePixel;
+use crate::util::{Color, RandomGenerator};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const MAX_RANGE: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborhoodShape {
+    /// A square of side `2 * range + 1`
+    Moore,
+    /// A diamond, out to a Manhattan distance of `range`
+    VonNeumann,
+    /// A disk, out to a Euclidean distance of `range + 0.5` as in Golly
+    Circular,
+}
+impl NeighborhoodShape {
+    fn neighborhood(self, range: GridInt) -> Neighborhood<'static> {
+        match self {
+            Self::Moore => Neighborhood::moore(range),
+            Self::VonNeumann => Neighborhood::von_neumann(range),
+            Self::Circular => Neighborhood::circular(range as f32 + 0.5),
+        }
+    }
+}
+
+/// A totalistic rule counting live cells out to a `range` of up to 10, in
+/// Golly's notation, e.g. `R5,C0,M1,S34..58,B34..45,NM` for Bosco's rule.
+/// As in Generations, rules with more than 2 `states` have dying cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LtlRule {
+    pub range: u8,
+    pub states: u8,
+    /// Whether a cell counts itself
+    pub middle: bool,
+    pub survive: (u32, u32),
+    pub birth: (u32, u32),
+    pub shape: NeighborhoodShape,
+}
+impl Default for LtlRule {
+    fn default() -> Self {
+        "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap()
+    }
+}
+impl FromStr for LtlRule {
+    type Err = String;
+    fn from_str(rule: &str) -> Result<Self, String> {
+        let error = |why: String| format!("invalid rule `{}`: {}", rule, why);
+        let interval = |value: &str| {
+            let (min, max) = value.split_once("..").unwrap_or((value, value));
+            match (min.parse(), max.parse()) {
+                (Ok(min), Ok(max)) if min <= max => Ok((min, max)),
+                _ => Err(error(format!("`{}` is not an interval like 34..58", value))),
+            }
+        };
+        let mut parsed = Self {
+            range: 0,
+            states: 2,
+            middle: false,
+            survive: (1, 0),
+            birth: (1, 0),
+            shape: NeighborhoodShape::Moore,
+        };
+        for item in rule.split(',').map(str::trim) {
+            let (key, value) = item.split_at(item.chars().next().map_or(0, char::len_utf8));
+            match (key, value) {
+                ("R", _) => {
+                    parsed.range = value
+                        .parse()
+                        .ok()
+                        .filter(|range| (1..=MAX_RANGE).contains(range))
+                        .ok_or_else(|| error(format!("the range should be 1 to {}", MAX_RANGE)))?
+                }
+                // Both C0 and C2 are two state rules
+                ("C", _) => {
+                    parsed.states = match value.parse() {
+                        Ok(0) => 2,
+                        Ok(states) if states >= 2 => states,
+                        _ => return Err(error(format!("`{}` is not a number of states", item))),
+                    }
+                }
+                ("M", "0" | "1") => parsed.middle = value == "1",
+                ("S", _) => parsed.survive = interval(value)?,
+                ("B", _) => parsed.birth = interval(value)?,
+                ("N", "M") => parsed.shape = NeighborhoodShape::Moore,
+                ("N", "N") => parsed.shape = NeighborhoodShape::VonNeumann,
+                ("N", "C") => parsed.shape = NeighborhoodShape::Circular,
+                _ => return Err(error(format!("unknown item `{}`", item))),
+            }
+        }
+        if parsed.range == 0
+            || parsed.survive.0 > parsed.survive.1
+            || parsed.birth.0 > parsed.birth.1
+        {
+            return Err(error("expected at least R, S and B".to_string()));
+        }
+        Ok(parsed)
+    }
+}
+impl fmt::Display for LtlRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let states = if self.states == 2 { 0 } else { self.states };
+        let shape = match self.shape {
+            NeighborhoodShape::Moore => "M",
+            NeighborhoodShape::VonNeumann => "N",
+            NeighborhoodShape::Circular => "C",
+        };
+        write!(
+            f,
+            "R{},C{},M{},S{}..{},B{}..{},N{}",
+            self.range,
+            states,
+            self.middle as u8,
+            self.survive.0,
+            self.survive.1,
+            self.birth.0,
+            self.birth.1,
+            shape
+        )
+    }
+}
+impl TryFrom<String> for LtlRule {
+    type Error = String;
+    fn try_from(rule: String) -> Result<Self, String> {
+        rule.parse()
+    }
+}
+impl From<LtlRule> for String {
+    fn from(rule: LtlRule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// The `[larger_than_life]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LargerThanLifeParams {
+    /// e.g. `R5,C0,M1,S34..58,B34..45,NM` for Bosco's rule. The last item picks
+    /// the neighborhood: `NM` for a square, `NN` for a diamond, `NC` for a disk.
+    pub rule: LtlRule,
+    /// Fraction of live cells in a random grid, the rest are dead
+    pub initial_fill: f32,
+}
+impl Default for LargerThanLifeParams {
+    fn default() -> Self {
+        Self {
+            rule: LtlRule::default(),
+            initial_fill: 0.5,
+        }
+    }
+}
+
+/// State 0 is dead, 1 is alive, and the ones after that are dying. Neighborhoods
+/// are square grid ones, whatever the lattice.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct LargerThanLifeCell {
+    pub state: u8,
+}
+impl LargerThanLifeCell {
+    const DEAD: u8 = 0;
+    const ALIVE: u8 = 1;
+
+    fn count(&self) -> u32 {
+        (self.state == Self::ALIVE) as u32
+    }
+    /// The next state, given the number of live cells in the neighborhood.
+    fn next(&self, count: u32, rule: &LtlRule) -> Self {
+        let within = |(min, max): (u32, u32)| (min..=max).contains(&count);
+        let state = match self.state {
+            Self::DEAD if within(rule.birth) => Self::ALIVE,
+            Self::DEAD => Self::DEAD,
+            Self::ALIVE if within(rule.survive) => Self::ALIVE,
+            dying => dying.saturating_add(1),
+        };
+        let state = if state < rule.states {
+            state
+        } else {
+            Self::DEAD
+        };
+        Self { state }
+    }
+}
+impl BoundaryValue for LargerThanLifeCell {
+    fn from_value(value: f32) -> Option<Self> {
+        Some(Self {
+            state: value.max(0.0) as u8,
+        })
+    }
+}
+impl Superpose for LargerThanLifeCell {}
+impl FromPixel for LargerThanLifeCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &LargerThanLifeParams) -> Option<Self> {
+        Some(Self {
+            state: pixel.is_bright() as u8,
+        })
+    }
+}
+impl Randomize for LargerThanLifeCell {
+    fn random(
+        rng: &mut RandomGenerator,
+        _grid_pos: GridPos,
+        params: &LargerThanLifeParams,
+    ) -> Self {
+        let alive = randomize::f32_half_open_right(rng.next_u32()) < params.initial_fill;
+        Self { state: alive as u8 }
+    }
+}
+impl HasColor for LargerThanLifeCell {
+    fn draw(&self) -> Color {
+        match self.state {
+            Self::DEAD => [0, 0, 0, 0xff],
+            Self::ALIVE => [0xff, 0xe0, 0x40, 0xff],
+            // Fading to dark blue, one shade per dying state
+            dying => {
+                let fade = 0.7f32.powi(dying as i32 - 2);
+                [
+                    0x20,
+                    (0x80 as f32 * fade) as u8,
+                    (0xff as f32 * fade) as u8,
+                    0xff,
+                ]
+            }
+        }
+    }
+}
+impl Cell for LargerThanLifeCell {
+    type Params = LargerThanLifeParams;
+    // Counts neighbors one by one. `update_grid` is what normally runs.
+    fn update(&self, grid_view: GridView<Self>) -> Self {
+        let rule = grid_view.params().rule;
+        let neighborhood = rule.shape.neighborhood(rule.range as GridInt);
+        let count = grid_view
+            .neighbors(&neighborhood)
+            .map(Self::count)
+            .sum::<u32>()
+            + if rule.middle { self.count() } else { 0 };
+        self.next(count, &rule)
+    }
+    fn update_grid(grid: &Grid<Self>, params: &LargerThanLifeParams, next: &mut [Self]) -> bool {
+        let rule = params.rule;
+        let range = rule.range as GridInt;
+        let table = SummedAreaTable::new(grid, rule.range as usize, Self::count);
+        // Disks have no summed-area shortcut, but their offsets are the same everywhere
+        let disk = rule.shape.neighborhood(range);
+        let cells = grid.get_grid_pos_iter().zip(grid.cells.iter());
+        for ((grid_pos, cell), slot) in cells.zip(next.iter_mut()) {
+            let count = match rule.shape {
+                NeighborhoodShape::Moore => table.square_sum(grid_pos, range),
+                NeighborhoodShape::VonNeumann => table.diamond_sum(grid_pos, range),
+                NeighborhoodShape::Circular => {
+                    let view = GridView::new(grid_pos, grid, params);
+                    view.neighbors(&disk).map(Self::count).sum::<u32>() + cell.count()
+                }
+            };
+            let count = if rule.middle {
+                count
+            } else {
+                count - cell.count()
+            };
+            *slot = cell.next(count, &rule);
+        }
+        true
+    }
+
+    fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
+        if (target_pos.x == grid_pos.x) & (target_pos.y == grid_pos.y) {
+            self.state = (self.state != Self::ALIVE) as u8;
+        }
+    }
+
+    fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, alive: bool) {
+        self.state = alive as u8;
+    }
+    fn is_alive(&self) -> Option<bool> {
+        Some(self.state == Self::ALIVE)
+    }
+    fn rulestring(params: &LargerThanLifeParams) -> Option<String> {
+        Some(params.rule.to_string())
+    }
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let count = |states: &dyn Fn(u8) -> bool| {
+            grid.cells.iter().filter(|cell| states(cell.state)).count() as f64
+        };
+        vec![
+            ("population", count(&|state| state == Self::ALIVE)),
+            ("dying", count(&|state| state > Self::ALIVE)),
+        ]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["state"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.state as f32
+    }
+}
+
+#[test]
+fn test_larger_than_life() {
+    use crate::grid::boundary::{ConstantBoundary, PeriodicBoundary};
+
+    let bosco = LtlRule::default();
+    assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+    let rule = |text: &str| text.parse::<LtlRule>().map(|rule| rule.to_string());
+    assert_eq!(
+        rule("R2, C3, S2..4, B3, NN"),
+        Ok("R2,C3,M0,S2..4,B3..3,NN".to_string())
+    );
+    assert_eq!(NeighborhoodShape::Circular.neighborhood(2).len(), 20);
+    for bad in [
+        "R11,S1,B1",
+        "R2,S4..2,B1",
+        "R2,S1",
+        "R2,S1,B1,NX",
+        "R2,S1,B1,Q",
+    ] {
+        assert!(rule(bad).is_err(), "{}", bad);
+    }
+
+    // The summed-area table gives the same counts as going through the neighbors,
+    // with some dying cells thrown in, up to the highest state
+    let mut rng = RandomGenerator::seed(7, 0);
+    for (text, boundary) in [
+        ("R3,C0,M1,S6..12,B5..9,NM", PeriodicBoundary.into()),
+        ("R4,C4,M0,S3..9,B4..7,NN", ConstantBoundary::empty().into()),
+        ("R2,C3,M1,S4..9,B3..6,NC", PeriodicBoundary.into()),
+    ] {
+        let params = LargerThanLifeParams {
+            rule: text.parse().unwrap(),
+            ..Default::default()
+        };
+        let mut grid = Grid::<LargerThanLifeCell>::new_empty(13, 9, boundary);
+        for cell in grid.cells.iter_mut() {
+            cell.state = (rng.next_u32() % 3) as u8;
+        }
+        grid.cells[0].state = 255;
+        let mut next = grid.cells.clone();
+        assert!(LargerThanLifeCell::update_grid(&grid, &params, &mut next));
+        for (grid_pos, fast) in grid.get_grid_pos_iter().zip(next.iter()) {
+            let view = GridView::new(grid_pos, &grid, &params);
+            let slow = grid.get_cell_at(grid_pos).update(view);
+            assert_eq!(fast.state, slow.state, "{} at {:?}", text, grid_pos);
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{gauss, generate_seed, map_to_unit_interval, RandomGenerator};
+use crate::grid::Grid;
+use crate::util::{cell_rng, gauss, map_to_unit_interval, RandomGenerator};
+use serde::{Deserialize, Serialize};
 
 type ParticleCount = u32;
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct ParticleCounter {
     pub up: ParticleCount,
     pub down: ParticleCount,
@@ -37,17 +39,17 @@ impl ParticleCounter {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ParticleDiffusionCell {
     particles: ParticleCounter,
     rng: RandomGenerator,
 }
+// Only a placeholder, `Grid::clear` gives every cell its own stream with `empty`
 impl Default for ParticleDiffusionCell {
     fn default() -> Self {
-        let rng: randomize::PCG32 = generate_seed().into();
         Self {
             particles: ParticleCounter::default(),
-            rng,
+            rng: RandomGenerator::seed(0, 0),
         }
     }
 }
@@ -59,13 +61,22 @@ impl ParticleDiffusionCell {
             + grid_view.get_cell_at_coord(0, -1).particles.down
     }
 }
+impl BoundaryValue for ParticleDiffusionCell {}
+impl Superpose for ParticleDiffusionCell {}
+impl FromPixel for ParticleDiffusionCell {}
 impl Randomize for ParticleDiffusionCell {
-    fn random(_rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self {
-        let mut rng: randomize::PCG32 = generate_seed().into();
+    fn random(rng: &mut RandomGenerator, grid_pos: GridPos, _params: &()) -> Self {
+        let mut rng = cell_rng(rng, grid_pos);
         let n = randomize::RandRangeU32::new(0, 5).sample(&mut rng);
         let particles = ParticleCounter::randomize_n(n, &mut rng);
         Self { particles, rng }
     }
+    fn empty(rng: &mut RandomGenerator, grid_pos: GridPos) -> Self {
+        Self {
+            particles: ParticleCounter::default(),
+            rng: cell_rng(rng, grid_pos),
+        }
+    }
 }
 impl HasColor for ParticleDiffusionCell {
     fn draw(&self) -> [u8; 4] {
@@ -76,6 +87,7 @@ impl HasColor for ParticleDiffusionCell {
 }
 
 impl Cell for ParticleDiffusionCell {
+    type Params = ();
     fn update(&self, grid_view: GridView<Self>) -> Self {
         let n = Self::get_n_incoming(grid_view);
         let mut rng = self.rng.clone();
@@ -86,8 +98,30 @@ impl Cell for ParticleDiffusionCell {
     fn toggle(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos) {}
 
     fn line_action(&mut self, target_pos: &GridPos, grid_pos: &GridPos, _alive: bool) {
-        let mut rng: randomize::PCG32 = generate_seed().into();
         let gauss_value = gauss(10.0, [20.0, 20.0], &target_pos, &grid_pos);
-        self.particles = ParticleCounter::randomize_n(gauss_value as ParticleCount, &mut rng);
+        self.particles = ParticleCounter::randomize_n(gauss_value as ParticleCount, &mut self.rng);
+    }
+    fn observables(grid: &Grid<Self>, _params: &()) -> Vec<(&'static str, f64)> {
+        let particles = grid.cells.iter().map(|c| c.particles.total() as f64).sum();
+        vec![("particles", particles)]
     }
+    fn field_names() -> &'static [&'static str] {
+        &["particles"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.particles.total() as f32
+    }
+}
+
+#[test]
+fn test_cleared_streams() {
+    use crate::grid::boundary::PeriodicBoundary;
+
+    // Cells of a cleared grid don't all draw the same numbers
+    let mut grid = Grid::<ParticleDiffusionCell>::new_empty(4, 3, PeriodicBoundary.into());
+    grid.clear();
+    let mut draws: Vec<_> = grid.cells.iter_mut().map(|c| c.rng.next_u32()).collect();
+    draws.sort_unstable();
+    draws.dedup();
+    assert_eq!(draws.len(), 12);
 }
@@ -0,0 +1,207 @@
+use crate::cell::{BoundaryValue, Cell, CoupledCell, FromPixel, HasColor, Randomize, Superpose};
+use crate::coupled_system::Fields;
+use crate::grid::grid_pos::GridPos;
+use crate::grid::grid_view::GridView;
+use crate::grid::Grid;
+use crate::image::ImagePixel;
+use crate::util::{gauss, RandomGenerator};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+type Float = f32;
+
+pub const PREY_FIELD: &str = "prey";
+pub const PREDATOR_FIELD: &str = "predator";
+
+/// The `[predator_prey]` section of the config, shared by both fields.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredatorPreyParams {
+    pub dt: Float,
+    pub prey_diffusion: Float,
+    pub predator_diffusion: Float,
+    pub prey_growth: Float,
+    pub predation: Float,
+    pub conversion: Float,
+    pub predator_death: Float,
+}
+impl Default for PredatorPreyParams {
+    fn default() -> Self {
+        Self {
+            dt: 0.1,
+            prey_diffusion: 1.0,
+            predator_diffusion: 0.5,
+            prey_growth: 1.0,
+            predation: 2.0,
+            conversion: 1.0,
+            predator_death: 0.4,
+        }
+    }
+}
+
+fn laplace<C: Cell>(grid_view: &GridView<C>, density: fn(&C) -> Float) -> Float {
+    grid_view
+        .laplace_neighbors()
+        .map(|(weight, cell)| weight * density(cell))
+        .sum()
+}
+
+fn shade(density: Float) -> u8 {
+    (density.clamp(0.0, 1.0) * (0xff as Float)) as u8
+}
+
+/// Prey density of a diffusive Lotka-Volterra model with logistic prey growth.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PreyCell {
+    pub density: Float,
+}
+impl PreyCell {
+    fn step(&self, grid_view: &GridView<Self>, predators: Float) -> Self {
+        let p = grid_view.params();
+        let u = self.density;
+        let du = p.prey_diffusion * laplace(grid_view, |c| c.density)
+            + p.prey_growth * u * (1.0 - u)
+            - p.predation * u * predators;
+        Self {
+            density: (u + p.dt * du).max(0.0),
+        }
+    }
+}
+impl BoundaryValue for PreyCell {
+    fn from_value(density: Float) -> Option<Self> {
+        Some(Self { density })
+    }
+}
+impl Superpose for PreyCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            density: self.density + amplitude.re,
+        })
+    }
+}
+impl FromPixel for PreyCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &PredatorPreyParams) -> Option<Self> {
+        Self::from_value(pixel.brightness())
+    }
+}
+impl Randomize for PreyCell {
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &PredatorPreyParams) -> Self {
+        Self {
+            density: randomize::f32_half_open_right(rng.next_u32()),
+        }
+    }
+}
+impl HasColor for PreyCell {
+    fn draw(&self) -> [u8; 4] {
+        [0, shade(self.density), 0, 0]
+    }
+}
+impl Cell for PreyCell {
+    type Params = PredatorPreyParams;
+    fn update(&self, grid_view: GridView<Self>) -> Self {
+        self.step(&grid_view, 0.0)
+    }
+
+    fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
+        self.density += gauss(1.0, [10.0, 10.0], target_pos, grid_pos);
+    }
+
+    fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let mass = grid.cells.iter().map(|cell| cell.density as f64).sum();
+        vec![("mass", mass)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["density"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.density
+    }
+}
+impl CoupledCell for PreyCell {
+    fn update_coupled(&self, grid_view: GridView<Self>, fields: &Fields) -> Self {
+        let predators = fields
+            .view::<PredatorCell>(PREDATOR_FIELD, grid_view.origin)
+            .map_or(0.0, |view| view.get_cell_at_coord(0, 0).density);
+        self.step(&grid_view, predators)
+    }
+}
+
+/// Predator density of a diffusive Lotka-Volterra model.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PredatorCell {
+    pub density: Float,
+}
+impl PredatorCell {
+    fn step(&self, grid_view: &GridView<Self>, prey: Float) -> Self {
+        let p = grid_view.params();
+        let v = self.density;
+        let dv = p.predator_diffusion * laplace(grid_view, |c| c.density) + p.conversion * prey * v
+            - p.predator_death * v;
+        Self {
+            density: (v + p.dt * dv).max(0.0),
+        }
+    }
+}
+impl BoundaryValue for PredatorCell {
+    fn from_value(density: Float) -> Option<Self> {
+        Some(Self { density })
+    }
+}
+impl Superpose for PredatorCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            density: self.density + amplitude.re,
+        })
+    }
+}
+impl FromPixel for PredatorCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &PredatorPreyParams) -> Option<Self> {
+        Self::from_value(pixel.brightness())
+    }
+}
+impl Randomize for PredatorCell {
+    // Start from a few scattered predators
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &PredatorPreyParams) -> Self {
+        let density = match randomize::f32_half_open_right(rng.next_u32()) {
+            x if x < 0.02 => 1.0,
+            _ => 0.0,
+        };
+        Self { density }
+    }
+}
+impl HasColor for PredatorCell {
+    fn draw(&self) -> [u8; 4] {
+        [shade(self.density), 0, 0, 0]
+    }
+}
+impl Cell for PredatorCell {
+    type Params = PredatorPreyParams;
+    fn update(&self, grid_view: GridView<Self>) -> Self {
+        self.step(&grid_view, 0.0)
+    }
+
+    fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
+        self.density += gauss(1.0, [10.0, 10.0], target_pos, grid_pos);
+    }
+
+    fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let mass = grid.cells.iter().map(|cell| cell.density as f64).sum();
+        vec![("mass", mass)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["density"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.density
+    }
+}
+impl CoupledCell for PredatorCell {
+    fn update_coupled(&self, grid_view: GridView<Self>, fields: &Fields) -> Self {
+        let prey = fields
+            .view::<PreyCell>(PREY_FIELD, grid_view.origin)
+            .map_or(0.0, |view| view.get_cell_at_coord(0, 0).density);
+        self.step(&grid_view, prey)
+    }
+}
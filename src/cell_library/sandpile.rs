@@ -1,24 +1,37 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
+use crate::grid::lattice::Lattice;
+use crate::grid::Grid;
+use crate::image::ImagePixel;
 use crate::util::{Color, RandomGenerator};
+use num_complex::Complex32;
 use randomize::RandRangeU32;
+use serde::{Deserialize, Serialize};
 
-const CRITICAL_HEIGHT: i32 = 4;
+/// The `[sandpile]` section of the config.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandpileParams {
+    /// Grains at which a cell topples, giving one to each edge neighbor. 0 for the
+    /// number of edge neighbors: 4 on a square lattice, 6 on a hexagonal one and 3
+    /// on a triangular one. Higher piles lose their extra grains when they topple.
+    pub critical_height: u32,
+}
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AbelianSandpileCell {
     pub height: i32,
 }
 impl AbelianSandpileCell {
-    fn get_neighbor_sand(grid_view: &GridView<Self>) -> i32 {
-        [(1, 0), (-1, 0), (0, 1), (0, -1)]
-            .iter()
-            .map(|(di, dj)| grid_view.get_cell_at_coord(*di, *dj).will_topple() as i32)
+    fn get_neighbor_sand(grid_view: &GridView<Self>, critical_height: i32) -> i32 {
+        grid_view
+            .edge_neighbors()
+            .map(|cell| cell.will_topple(critical_height) as i32)
             .sum()
     }
-    fn will_topple(&self) -> bool {
-        self.height >= CRITICAL_HEIGHT
+    fn will_topple(&self, critical_height: i32) -> bool {
+        self.height >= critical_height
     }
     fn colormap_1(&self) -> Color {
         match self.height {
@@ -35,9 +48,33 @@ impl AbelianSandpileCell {
         [shade, shade / 2, shade / 4, 0]
     }
 }
+impl BoundaryValue for AbelianSandpileCell {
+    fn from_value(value: f32) -> Option<Self> {
+        Some(Self {
+            height: value as i32,
+        })
+    }
+}
+impl Superpose for AbelianSandpileCell {
+    // Amplitudes are numbers of grains
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            height: self.height + amplitude.re.round() as i32,
+        })
+    }
+}
+impl FromPixel for AbelianSandpileCell {
+    // Black is empty and white is the highest stable pile
+    fn from_pixel(pixel: &ImagePixel, params: &SandpileParams) -> Option<Self> {
+        let highest = params.critical_height as f32 - 1.0;
+        Some(Self {
+            height: (pixel.brightness() * highest).round() as i32,
+        })
+    }
+}
 impl Randomize for AbelianSandpileCell {
-    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self {
-        let rand_int = RandRangeU32::new(0, 4);
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, params: &SandpileParams) -> Self {
+        let rand_int = RandRangeU32::new(0, params.critical_height - 1);
         Self {
             height: rand_int.sample(rng) as i32,
         }
@@ -49,10 +86,12 @@ impl HasColor for AbelianSandpileCell {
     }
 }
 impl Cell for AbelianSandpileCell {
+    type Params = SandpileParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        let sand_in = Self::get_neighbor_sand(&grid_view);
-        let height = match self.will_topple() {
-            true => self.height - CRITICAL_HEIGHT + sand_in,
+        let critical_height = grid_view.params().critical_height as i32;
+        let sand_in = Self::get_neighbor_sand(&grid_view, critical_height);
+        let height = match self.will_topple(critical_height) {
+            true => self.height - critical_height + sand_in,
             false => self.height + sand_in,
         };
         Self { height }
@@ -65,4 +104,55 @@ impl Cell for AbelianSandpileCell {
     }
 
     fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+    fn on_lattice(params: SandpileParams, lattice: Lattice) -> Result<SandpileParams, String> {
+        // The same for every cell of a lattice
+        let neighbors = lattice.edge_neighborhood(GridPos::new(0, 0)).len() as u32;
+        match params.critical_height {
+            0 => Ok(SandpileParams {
+                critical_height: neighbors,
+            }),
+            height if height < neighbors => Err(format!(
+                "the critical_height should be at least {} on a {:?} lattice, \
+                where a toppling cell gives a grain to each edge neighbor",
+                neighbors, lattice
+            )),
+            _ => Ok(params),
+        }
+    }
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let sand = grid.cells.iter().map(|cell| cell.height as f64).sum();
+        vec![("sand", sand)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["height"]
+    }
+    fn field_value(&self, _index: usize) -> f32 {
+        self.height as f32
+    }
+}
+
+#[test]
+fn test_critical_height() {
+    let resolved = |critical_height, lattice| {
+        AbelianSandpileCell::on_lattice(SandpileParams { critical_height }, lattice)
+            .map(|params| params.critical_height)
+    };
+    assert_eq!(resolved(0, Lattice::Square), Ok(4));
+    assert_eq!(resolved(0, Lattice::Hexagonal), Ok(6));
+    assert_eq!(resolved(5, Lattice::Triangular), Ok(5));
+    assert!(resolved(4, Lattice::Hexagonal).is_err());
+
+    // Random and white piles are as high as they can be without toppling
+    let params = SandpileParams { critical_height: 7 };
+    let mut rng = RandomGenerator::seed(3, 0);
+    let highest = (0..200)
+        .map(|_| AbelianSandpileCell::random(&mut rng, GridPos::new(0, 0), &params).height)
+        .max();
+    assert_eq!(highest, Some(6));
+    let white = ImagePixel {
+        rgb: [1.0; 3],
+        threshold: 0.5,
+    };
+    let pile = AbelianSandpileCell::from_pixel(&white, &params).unwrap();
+    assert_eq!(pile.height, 6);
 }
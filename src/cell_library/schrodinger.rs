@@ -1,25 +1,35 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, Cell3, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{
-    complex_to_hue, gauss, map_from_unit_interval, stencil_coords, RandomGenerator,
-    SECOND_ORDER_CENTRAL,
-};
+use crate::grid::Grid;
+use crate::grid3d::GridView3;
+use crate::util::{complex_to_hue, gauss, map_from_unit_interval, RandomGenerator};
 use num_complex::Complex32;
 use palette::{Hsv, LinSrgb, Pixel};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 
 type Float = f32;
 
-const DT: Float = 0.1;
+/// The `[schrodinger]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchrodingerParams {
+    pub dt: Float,
+}
+impl Default for SchrodingerParams {
+    fn default() -> Self {
+        Self { dt: 0.1 }
+    }
+}
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct SchrodingerCell {
     real: Float,
     imag: Float,
     update_phase: CellDataLabel,
 }
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 enum CellDataLabel {
     Real,
     Imag,
@@ -32,11 +42,9 @@ impl Default for CellDataLabel {
 
 impl SchrodingerCell {
     fn laplace(grid_view: &GridView<Self>, label: CellDataLabel) -> Float {
-        SECOND_ORDER_CENTRAL
-            .iter()
-            .flatten()
-            .zip(stencil_coords(3, 3))
-            .map(|(weight, dpos)| weight * grid_view.get_cell_at(dpos).get_data(label))
+        grid_view
+            .laplace_neighbors()
+            .map(|(weight, cell)| weight * cell.get_data(label))
             .sum()
     }
     fn one_dimensional_hamiltonian(
@@ -53,9 +61,40 @@ impl SchrodingerCell {
             .sum();
         -laplace
     }
-    fn hamiltonian(&self, grid_view: GridView<Self>, label: CellDataLabel) -> Float {
-        -Self::laplace(&grid_view, label)
-            + Self::potential(&grid_view.origin) * self.get_data(label)
+    fn hamiltonian(&self, grid_view: &GridView<Self>, label: CellDataLabel) -> Float {
+        -Self::laplace(grid_view, label) + Self::potential(&grid_view.origin) * self.get_data(label)
+    }
+    fn hamiltonian_3d(&self, grid_view: &GridView3<Self>, label: CellDataLabel) -> Float {
+        let laplace: Float = grid_view
+            .laplace_neighbors()
+            .map(|(weight, cell)| weight * cell.get_data(label))
+            .sum();
+        -laplace + Self::potential(&grid_view.origin.xy()) * self.get_data(label)
+    }
+    // Alternate between updating the real and imaginary parts
+    fn step(&self, dt: Float, hamiltonian: impl Fn(CellDataLabel) -> Float) -> Self {
+        match self.update_phase {
+            CellDataLabel::Real => {
+                let real = self.real + dt * hamiltonian(CellDataLabel::Imag);
+                let imag = self.imag;
+                let update_phase = CellDataLabel::Imag;
+                Self {
+                    real,
+                    imag,
+                    update_phase,
+                }
+            }
+            CellDataLabel::Imag => {
+                let real = self.real;
+                let imag = self.imag - dt * hamiltonian(CellDataLabel::Real);
+                let update_phase = CellDataLabel::Real;
+                Self {
+                    real,
+                    imag,
+                    update_phase,
+                }
+            }
+        }
     }
     fn get_data(&self, label: CellDataLabel) -> Float {
         match label {
@@ -108,8 +147,27 @@ impl SchrodingerCell {
         }
     }
 }
+impl BoundaryValue for SchrodingerCell {
+    fn negate(&self) -> Option<Self> {
+        Some(Self {
+            real: -self.real,
+            imag: -self.imag,
+            update_phase: self.update_phase,
+        })
+    }
+}
+impl Superpose for SchrodingerCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            real: self.real + amplitude.re,
+            imag: self.imag + amplitude.im,
+            ..*self
+        })
+    }
+}
+impl FromPixel for SchrodingerCell {}
 impl Randomize for SchrodingerCell {
-    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self {
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &SchrodingerParams) -> Self {
         Self {
             real: 0.1
                 * map_from_unit_interval(randomize::f32_half_open_right(rng.next_u32()), -1.0, 1.0),
@@ -132,29 +190,10 @@ impl HasColor for SchrodingerCell {
     }
 }
 impl Cell for SchrodingerCell {
+    type Params = SchrodingerParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        match self.update_phase {
-            CellDataLabel::Real => {
-                let real = self.real + DT * self.hamiltonian(grid_view, CellDataLabel::Imag);
-                let imag = self.imag;
-                let update_phase = CellDataLabel::Imag;
-                Self {
-                    real,
-                    imag,
-                    update_phase,
-                }
-            }
-            CellDataLabel::Imag => {
-                let real = self.real;
-                let imag = self.imag - DT * self.hamiltonian(grid_view, CellDataLabel::Real);
-                let update_phase = CellDataLabel::Real;
-                Self {
-                    real,
-                    imag,
-                    update_phase,
-                }
-            }
-        }
+        let dt = grid_view.params().dt;
+        self.step(dt, |label| self.hamiltonian(&grid_view, label))
     }
 
     fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
@@ -172,4 +211,27 @@ impl Cell for SchrodingerCell {
     }
 
     fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+    fn observables(grid: &Grid<Self>, _params: &Self::Params) -> Vec<(&'static str, f64)> {
+        let norm = grid
+            .cells
+            .iter()
+            .map(|cell| (cell.real * cell.real + cell.imag * cell.imag) as f64)
+            .sum();
+        vec![("norm", norm)]
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["real", "imag"]
+    }
+    fn field_value(&self, index: usize) -> f32 {
+        match index {
+            0 => self.real,
+            _ => self.imag,
+        }
+    }
+}
+impl Cell3 for SchrodingerCell {
+    fn update_3d(&self, grid_view: GridView3<Self>) -> Self {
+        let dt = grid_view.params().dt;
+        self.step(dt, |label| self.hamiltonian_3d(&grid_view, label))
+    }
 }
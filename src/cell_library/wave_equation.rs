@@ -1,30 +1,56 @@
-use crate::cell::{Cell, HasColor, Randomize};
+use crate::cell::{BoundaryValue, Cell, Cell3, FromPixel, HasColor, Randomize, Superpose};
 use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{
-    gauss, map_to_unit_interval, stencil_coords, RandomGenerator, SECOND_ORDER_CENTRAL,
-};
+use crate::grid3d::GridView3;
+use crate::image::ImagePixel;
+use crate::integrator::{FieldCell, FieldState, Phase};
+use crate::util::{gauss, map_to_unit_interval, RandomGenerator};
+use num_complex::Complex32;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 type Float = f32;
 
-const DT: Float = 0.01;
-const DAMPING: Float = 0.01;
-const MASS: Float = 0.0;
+/// The `[wave]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaveParams {
+    pub dt: Float,
+    pub damping: Float,
+    pub mass: Float,
+}
+impl Default for WaveParams {
+    fn default() -> Self {
+        Self {
+            dt: 0.01,
+            damping: 0.01,
+            mass: 0.0,
+        }
+    }
+}
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct WaveCell {
     value: Float,
     velocity: Float,
 }
 impl WaveCell {
-    fn laplace(grid_view: GridView<Self>) -> Float {
-        SECOND_ORDER_CENTRAL
-            .iter()
-            .flatten()
-            .zip(stencil_coords(3, 3))
-            .map(|(weight, dpos)| weight * grid_view.get_cell_at(dpos).value)
+    fn laplace(grid_view: &GridView<Self>) -> Float {
+        grid_view
+            .laplace_neighbors()
+            .map(|(weight, cell)| weight * cell.value)
             .sum()
     }
+    fn phase_derivative(&self, laplace: Float, speed: Float, params: &WaveParams) -> Phase<Float> {
+        Phase {
+            position: self.velocity,
+            velocity: speed * laplace - params.damping * self.velocity - params.mass * self.value,
+        }
+    }
+    fn step(&self, laplace: Float, speed: Float, params: &WaveParams) -> Self {
+        let derivative = self.phase_derivative(laplace, speed, params);
+        let state = self.state().kick(derivative, params.dt).drift(params.dt);
+        self.with_state(state)
+    }
     fn speed(grid_pos: &GridPos) -> Float {
         Self::single_slit_speed(grid_pos)
     }
@@ -54,8 +80,35 @@ impl WaveCell {
         }
     }
 }
+impl BoundaryValue for WaveCell {
+    fn from_value(value: Float) -> Option<Self> {
+        Some(Self {
+            value,
+            velocity: 0.0,
+        })
+    }
+    fn negate(&self) -> Option<Self> {
+        Some(Self {
+            value: -self.value,
+            velocity: -self.velocity,
+        })
+    }
+}
+impl Superpose for WaveCell {
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            value: self.value + amplitude.re,
+            ..*self
+        })
+    }
+}
+impl FromPixel for WaveCell {
+    fn from_pixel(pixel: &ImagePixel, _params: &WaveParams) -> Option<Self> {
+        Self::from_value(pixel.brightness())
+    }
+}
 impl Randomize for WaveCell {
-    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos) -> Self {
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &WaveParams) -> Self {
         let value = randomize::f32_half_open_right(rng.next_u32()) * 2.0 - 1.0;
         Self {
             value,
@@ -73,12 +126,9 @@ impl HasColor for WaveCell {
     }
 }
 impl Cell for WaveCell {
+    type Params = WaveParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        let velocity = self.velocity * (1.0 - DAMPING * DT)
-            + Self::speed(&grid_view.origin) * Self::laplace(grid_view) * DT
-            - self.value * MASS * DT;
-        let value = self.value + velocity * DT;
-        Self { value, velocity }
+        self.semi_implicit_euler(&grid_view)
     }
 
     fn toggle(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos) {}
@@ -96,4 +146,51 @@ impl Cell for WaveCell {
         self.value += gauss_value * phase.cos();
         self.velocity += 1.0 * gauss_value * phase.sin();
     }
+    fn field_names() -> &'static [&'static str] {
+        &["value", "velocity"]
+    }
+    fn field_value(&self, index: usize) -> f32 {
+        match index {
+            0 => self.value,
+            _ => self.velocity,
+        }
+    }
+}
+impl FieldCell for WaveCell {
+    type State = Phase<Float>;
+    fn dt(params: &WaveParams) -> Float {
+        params.dt
+    }
+    fn state(&self) -> Phase<Float> {
+        Phase {
+            position: self.value,
+            velocity: self.velocity,
+        }
+    }
+    fn with_state(&self, state: Phase<Float>) -> Self {
+        Self {
+            value: state.position,
+            velocity: state.velocity,
+        }
+    }
+    fn derivative(&self, grid_view: &GridView<Self>) -> Phase<Float> {
+        self.phase_derivative(
+            Self::laplace(grid_view),
+            Self::speed(&grid_view.origin),
+            grid_view.params(),
+        )
+    }
+}
+impl Cell3 for WaveCell {
+    fn update_3d(&self, grid_view: GridView3<Self>) -> Self {
+        let laplace = grid_view
+            .laplace_neighbors()
+            .map(|(weight, cell)| weight * cell.value)
+            .sum();
+        self.step(
+            laplace,
+            Self::speed(&grid_view.origin.xy()),
+            grid_view.params(),
+        )
+    }
 }
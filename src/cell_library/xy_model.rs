@@ -1,51 +1,99 @@
-use crate::cell::{Cell, HasColor, Randomize};
-use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::cell::{BoundaryValue, Cell, Cell3, FromPixel, HasColor, Randomize, Superpose};
+use crate::grid::grid_pos::GridPos;
 use crate::grid::grid_view::GridView;
-use crate::util::{gauss, map_to_unit_interval, modulo, RandomGenerator};
+use crate::grid::Grid;
+use crate::grid3d::GridView3;
+use crate::image::ImagePixel;
+use crate::integrator::{FieldCell, FieldState, Phase};
+use crate::util::{gauss, generate_seed, map_to_unit_interval, modulo, normal, RandomGenerator};
+use num_complex::Complex32;
 use palette::{Hsv, Pixel, Srgb};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
+use winit::event::VirtualKeyCode;
 
 type Float = f32;
-const J: Float = 1.0;
-const H: Float = 0.0;
-const DAMPING: Float = 0.0;
 const TWO_PI: Float = 2.0 * PI;
-const DT: Float = 0.01;
-const TEMPERATURE: Float = 0.1;
 
-#[derive(Clone, Default)]
+/// The `[xy_model]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XYModelParams {
+    /// Coupling between neighboring spins
+    pub j: Float,
+    /// External field
+    pub h: Float,
+    pub damping: Float,
+    pub dt: Float,
+    /// Temperature of the heat bath, which the spins only feel through `damping`
+    pub temperature: Float,
+}
+impl Default for XYModelParams {
+    fn default() -> Self {
+        Self {
+            j: 1.0,
+            h: 0.0,
+            damping: 0.0,
+            dt: 0.01,
+            temperature: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct XYModelCell {
     pub value: Float,
     pub velocity: Float,
 }
 impl XYModelCell {
-    fn get_energy_from(&self, grid_view: &GridView<Self>, di: GridInt, dj: GridInt) -> Float {
-        let other_cell = grid_view.get_cell_at_coord(di, dj);
+    fn get_energy_from(&self, other_cell: &Self, params: &XYModelParams) -> Float {
         let self_angle = TWO_PI * self.value;
         let delta_angle = self_angle - TWO_PI * other_cell.value;
-        -J * (delta_angle.cos())
+        -params.j * (delta_angle.cos())
     }
     pub fn get_energy(&self, grid_view: &GridView<Self>) -> Float {
+        let params = grid_view.params();
         // this term is called 'kinetic' in processing, but it looks like an external field term
-        let kinetic = -H * (TWO_PI * grid_view.get_cell_at_coord(0, 0).value).cos();
-        let potential: Float = [(1, 0), (-1, 0), (0, 1), (0, -1)]
-            .iter()
-            .map(|(di, dj)| self.get_energy_from(grid_view, *di, *dj))
+        let kinetic = -params.h * (TWO_PI * grid_view.get_cell_at_coord(0, 0).value).cos();
+        let potential: Float = grid_view
+            .edge_neighbors()
+            .map(|other_cell| self.get_energy_from(other_cell, params))
             .sum();
         return kinetic + potential;
     }
-    fn get_force_from(&self, grid_view: &GridView<Self>, di: GridInt, dj: GridInt) -> Float {
-        let other_cell = grid_view.get_cell_at_coord(di, dj);
+    fn total_energy(grid: &Grid<Self>, params: &XYModelParams) -> Float {
+        grid.get_grid_pos_iter()
+            .map(|p| {
+                grid.get_cell_at(p)
+                    .get_energy(&GridView::new(p, grid, params))
+            })
+            .sum()
+    }
+    fn get_force_from(&self, other_cell: &Self, params: &XYModelParams) -> Float {
         let self_angle = TWO_PI * self.value;
         let delta_angle = self_angle - TWO_PI * other_cell.value;
-        -J * (delta_angle.sin()) + H * (self_angle.sin())
+        -params.j * (delta_angle.sin()) + params.h * (self_angle.sin())
     }
-    fn get_force(&self, grid_view: &GridView<Self>) -> Float {
-        [(1, 0), (-1, 0), (0, 1), (0, -1)]
-            .iter()
-            .map(|(di, dj)| self.get_force_from(grid_view, *di, *dj))
+    fn get_force<'a>(
+        &self,
+        neighbors: impl Iterator<Item = &'a Self>,
+        params: &XYModelParams,
+    ) -> Float {
+        neighbors
+            .map(|other_cell| self.get_force_from(other_cell, params))
             .sum()
     }
+    fn phase_derivative(&self, force: Float, params: &XYModelParams) -> Phase<Float> {
+        Phase {
+            position: self.velocity,
+            velocity: force - params.damping * self.velocity,
+        }
+    }
+    fn step(&self, force: Float, params: &XYModelParams) -> Self {
+        let derivative = self.phase_derivative(force, params);
+        let state = self.state().kick(derivative, params.dt).drift(params.dt);
+        self.with_state(state)
+    }
     fn create_gauss(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
         let value = gauss(0.1, [10.0, 10.0], &target_pos, &grid_pos);
         self.value = modulo(self.value + value, 1.0);
@@ -58,17 +106,40 @@ impl XYModelCell {
         let value = map_to_unit_interval(dy.atan2(dx), -PI, PI);
         self.value = modulo(self.value + value, 1.0);
     }
-    fn thermal_update(&self, _grid_view: &GridView<Self>) -> Self {
-        // need rng here
-        todo!()
+}
+impl BoundaryValue for XYModelCell {
+    fn from_value(value: Float) -> Option<Self> {
+        Some(Self {
+            value: modulo(value, 1.0),
+            velocity: 0.0,
+        })
+    }
+    // The opposite spin, half a turn away, turning at the same rate
+    fn negate(&self) -> Option<Self> {
+        Some(Self {
+            value: modulo(self.value + 0.5, 1.0),
+            velocity: self.velocity,
+        })
+    }
+}
+impl Superpose for XYModelCell {
+    // Amplitudes are angles in turns
+    fn superpose(&self, amplitude: Complex32) -> Option<Self> {
+        Some(Self {
+            value: modulo(self.value + amplitude.re, 1.0),
+            ..*self
+        })
+    }
+}
+impl FromPixel for XYModelCell {
+    // The inverse of `draw`
+    fn from_pixel(pixel: &ImagePixel, _params: &XYModelParams) -> Option<Self> {
+        Self::from_value(pixel.hue())
     }
 }
 impl Randomize for XYModelCell {
-    fn random(rng: &mut RandomGenerator, grid_pos: GridPos) -> Self {
-        let value = (grid_pos.x / 10) * (grid_pos.y / 10);
-        let value = (value as Float) / 10.0;
-        let _value = value + randomize::f32_half_open_right(rng.next_u32()) * 0.0;
-        let value = randomize::f32_half_open_right(rng.next_u32()) * 1.0;
+    fn random(rng: &mut RandomGenerator, _grid_pos: GridPos, _params: &XYModelParams) -> Self {
+        let value = randomize::f32_half_open_right(rng.next_u32());
         Self {
             value,
             velocity: 0.0,
@@ -83,10 +154,9 @@ impl HasColor for XYModelCell {
     }
 }
 impl Cell for XYModelCell {
+    type Params = XYModelParams;
     fn update(&self, grid_view: GridView<Self>) -> Self {
-        let velocity = self.velocity * (1.0 - DAMPING * DT) + DT * self.get_force(&grid_view);
-        let value = modulo(self.value + DT * velocity, 1.0);
-        Self { value, velocity }
+        self.semi_implicit_euler(&grid_view)
     }
 
     fn toggle(&mut self, target_pos: &GridPos, grid_pos: &GridPos) {
@@ -94,8 +164,162 @@ impl Cell for XYModelCell {
     }
 
     fn line_action(&mut self, _target_pos: &GridPos, _grid_pos: &GridPos, _alive: bool) {}
+
+    // Langevin dynamics: random kicks balancing the damping, so that the spins
+    // settle at `temperature`
+    fn thermalize(cells: &mut [Self], params: &XYModelParams) -> bool {
+        if params.temperature <= 0.0 || params.damping <= 0.0 {
+            return false;
+        }
+        let kick = (2.0 * params.damping * params.temperature * params.dt).sqrt();
+        let mut rng: RandomGenerator = generate_seed().into();
+        for cell in cells.iter_mut() {
+            cell.velocity += kick * normal(&mut rng);
+        }
+        true
+    }
+
+    fn observables(grid: &Grid<Self>, params: &XYModelParams) -> Vec<(&'static str, f64)> {
+        // Length of the mean spin
+        let (x, y) = grid.cells.iter().fold((0.0, 0.0), |(x, y), cell| {
+            let angle = TWO_PI as f64 * cell.value as f64;
+            (x + angle.cos(), y + angle.sin())
+        });
+        let magnetization = x.hypot(y) / grid.cells.len() as f64;
+        vec![
+            ("energy", Self::total_energy(grid, params) as f64),
+            ("magnetization", magnetization),
+        ]
+    }
+    fn key_response(grid: &Grid<Self>, params: &XYModelParams, vkc: VirtualKeyCode) {
+        if vkc == VirtualKeyCode::E {
+            println!("Total energy {:?}", Self::total_energy(grid, params))
+        }
+    }
+    fn field_names() -> &'static [&'static str] {
+        &["value", "velocity"]
+    }
+    fn field_value(&self, index: usize) -> f32 {
+        match index {
+            0 => self.value,
+            _ => self.velocity,
+        }
+    }
+}
+
+impl FieldCell for XYModelCell {
+    type State = Phase<Float>;
+    fn dt(params: &XYModelParams) -> Float {
+        params.dt
+    }
+    fn state(&self) -> Phase<Float> {
+        Phase {
+            position: self.value,
+            velocity: self.velocity,
+        }
+    }
+    fn with_state(&self, state: Phase<Float>) -> Self {
+        Self {
+            value: modulo(state.position, 1.0),
+            velocity: state.velocity,
+        }
+    }
+    fn derivative(&self, grid_view: &GridView<Self>) -> Phase<Float> {
+        let params = grid_view.params();
+        self.phase_derivative(self.get_force(grid_view.edge_neighbors(), params), params)
+    }
+}
+
+impl Cell3 for XYModelCell {
+    fn update_3d(&self, grid_view: GridView3<Self>) -> Self {
+        let params = grid_view.params();
+        self.step(self.get_force(grid_view.edge_neighbors(), params), params)
+    }
 }
 
 // impl Boundary<XYModelCell> {
 //     fn free()
 // }
+
+#[test]
+fn test_thermal_equipartition() {
+    use crate::grid::boundary::PeriodicBoundary;
+    use crate::integrator::{IntegratedSystem, Integrator};
+
+    // Free spins in a heat bath, whose mean squared velocity is the temperature
+    let params = XYModelParams {
+        j: 0.0,
+        damping: 1.0,
+        temperature: 0.5,
+        ..Default::default()
+    };
+    let grid = Grid::<XYModelCell>::new_empty(20, 20, PeriodicBoundary.into());
+    let mut system = IntegratedSystem::new(grid, Integrator::SemiImplicitEuler)
+        .unwrap()
+        .with_params(params);
+    let mut sum = 0.0;
+    for step in 0..2000 {
+        system.update();
+        if step >= 1000 {
+            let cells = system.grid.cells.iter();
+            sum += cells.map(|cell| cell.velocity.powi(2)).sum::<Float>() / 400.0;
+        }
+    }
+    let mean = sum / 1000.0;
+    assert!((mean - 0.5).abs() < 0.05, "{}", mean);
+}
+
+#[test]
+fn test_thermal_kick_once_per_step() {
+    use crate::cell::System;
+    use crate::generic_system::{GenericSystem, GenericSystem3};
+    use crate::grid::boundary::PeriodicBoundary;
+    use crate::grid3d::Grid3;
+
+    // Free spins at rest get a single kick of variance `2 * damping * temperature * dt`
+    // per step, whether the grid is 2D or 3D, and updated in parallel or not
+    let params = XYModelParams {
+        j: 0.0,
+        damping: 1.0,
+        temperature: 0.5,
+        dt: 0.01,
+        ..Default::default()
+    };
+    let variance = |cells: &[XYModelCell]| {
+        let sum: Float = cells.iter().map(|cell| cell.velocity.powi(2)).sum();
+        sum / cells.len() as Float
+    };
+    let grid = Grid::<XYModelCell>::new_empty(100, 100, PeriodicBoundary.into());
+    let mut system = GenericSystem::new(grid).with_params(params.clone());
+    System::update(&mut system);
+    assert!((variance(&system.grid.cells) - 0.01).abs() < 0.001);
+
+    let grid = Grid3::<XYModelCell>::new_empty(25, 20, 20, PeriodicBoundary.into());
+    let mut system = GenericSystem3::new(grid).with_params(params);
+    system.update();
+    assert!((variance(&system.grid.cells) - 0.01).abs() < 0.001);
+}
+
+#[test]
+fn test_antiperiodic_spins() {
+    use crate::config::BoundaryConfig;
+
+    // Across the wrap, an aligned grid sees opposite spins, which cost energy at the edges
+    let boundary = BoundaryConfig::Antiperiodic.build().unwrap();
+    let mut grid = Grid::<XYModelCell>::new_empty(4, 3, boundary);
+    for cell in grid.cells.iter_mut() {
+        cell.value = 0.125;
+        cell.velocity = 0.5;
+    }
+    grid.refresh_boundary();
+    let across = grid.get_cell_at(GridPos::new(-1, 1));
+    assert_eq!((across.value, across.velocity), (0.625, 0.5));
+    let params = XYModelParams::default();
+    let energy = |x, y| {
+        let view = GridView::new(GridPos::new(x, y), &grid, &params);
+        grid.get_cell_at(view.origin).get_energy(&view)
+    };
+    assert!((energy(1, 1) + 4.0).abs() < 1e-5);
+    assert!((energy(0, 1) + 2.0).abs() < 1e-5);
+    assert!(energy(0, 0).abs() < 1e-5);
+}
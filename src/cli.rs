@@ -0,0 +1,175 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::life_pattern::Region;
+
+pub struct HeadlessOptions {
+    pub steps: u32,
+    // Render a frame every `frame_every` steps. 0 disables periodic frames.
+    pub frame_every: u32,
+    pub out_dir: PathBuf,
+    // Write a snapshot here after the last step
+    pub save: Option<PathBuf>,
+    // Write the final live cells as RLE here, only those in `region` if given
+    pub export_rle: Option<PathBuf>,
+    pub region: Option<Region>,
+    // Write the final frame as a PNG here
+    pub screenshot: Option<PathBuf>,
+    // Record every step to this GIF or PNG directory, per the `[recording]` settings
+    pub record: Option<PathBuf>,
+    // Write the final numeric fields as `.npy`, and every `npz_every` steps as `.npz`
+    pub npy: Option<PathBuf>,
+    pub npz: Option<PathBuf>,
+    pub npz_every: u32,
+}
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            steps: 100,
+            frame_every: 0,
+            out_dir: PathBuf::from("out"),
+            save: None,
+            export_rle: None,
+            region: None,
+            screenshot: None,
+            record: None,
+            npy: None,
+            npz: None,
+            npz_every: 1,
+        }
+    }
+}
+
+pub enum Command {
+    Window,
+    Headless(HeadlessOptions),
+    ListSystems,
+}
+
+pub struct Args {
+    pub command: Command,
+    pub config_path: PathBuf,
+    // `key=value` pairs applied on top of the config file, in order
+    pub overrides: Vec<(String, String)>,
+    // Start from this snapshot instead of a new grid
+    pub load: Option<PathBuf>,
+    // Write every interaction of a window session to this journal on exit
+    pub journal: Option<PathBuf>,
+    // Play back the interactions, system and seed of a journal
+    pub replay: Option<PathBuf>,
+}
+
+const USAGE: &str = "usage:
+    cellular [OPTIONS]
+    cellular [OPTIONS] headless [--steps N] [--frame-every K] [--out DIR] [--save PATH]
+                        [--export-rle PATH [--region X,Y,W,H]] [--screenshot PATH]
+                        [--record PATH] [--npy PATH] [--npz PATH [--npz-every K]]
+    cellular --list-systems
+options:
+    --config PATH       read the config from PATH instead of config.toml
+    --set KEY=VALUE     override a config value, e.g. --set xy_model.temperature=0.5
+    --load PATH         start from a snapshot, which F5 and F9 also save to and load from
+    --journal PATH      write the seed and every click and key press to PATH on exit
+    --replay PATH       play back a journal, in a window or headless. Neither works with --load
+keys:
+    right drag selects a region, which F6 exports to pattern.rle (or the whole grid)
+    F12 saves a PNG screenshot, upscaled by the screenshot_scale setting
+    F7 starts and stops recording, set up in the [recording] section
+    F10 saves the raw cell values as fields_STEP.npy";
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {:?}", flag, value))
+}
+
+fn parse_override(value: Option<String>) -> Result<(String, String), String> {
+    let value = value.ok_or_else(|| "missing value for --set".to_string())?;
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("--set expects KEY=VALUE, got {:?}", value)),
+    }
+}
+
+fn parse_headless_flag(
+    options: &mut HeadlessOptions,
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<(), String> {
+    match arg {
+        "--steps" => options.steps = parse_value(arg, args.next())?,
+        "--frame-every" => options.frame_every = parse_value(arg, args.next())?,
+        "--out" => options.out_dir = parse_value(arg, args.next())?,
+        "--save" => options.save = Some(parse_value(arg, args.next())?),
+        "--export-rle" => options.export_rle = Some(parse_value(arg, args.next())?),
+        "--region" => options.region = Some(parse_value(arg, args.next())?),
+        "--screenshot" => options.screenshot = Some(parse_value(arg, args.next())?),
+        "--record" => options.record = Some(parse_value(arg, args.next())?),
+        "--npy" => options.npy = Some(parse_value(arg, args.next())?),
+        "--npz" => options.npz = Some(parse_value(arg, args.next())?),
+        "--npz-every" => {
+            options.npz_every = parse_value(arg, args.next())?;
+            if options.npz_every == 0 {
+                return Err("--npz-every must be at least 1".to_string());
+            }
+        }
+        _ => return Err(format!("unknown argument {:?}\n{}", arg, USAGE)),
+    }
+    Ok(())
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        command: Command::Window,
+        config_path: PathBuf::from("config.toml"),
+        overrides: vec![],
+        load: None,
+        journal: None,
+        replay: None,
+    };
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), &mut parsed.command) {
+            ("--config", _) => parsed.config_path = parse_value(&arg, args.next())?,
+            ("--set", _) => parsed.overrides.push(parse_override(args.next())?),
+            ("--load", _) => parsed.load = Some(parse_value(&arg, args.next())?),
+            ("--journal", _) => parsed.journal = Some(parse_value(&arg, args.next())?),
+            ("--replay", _) => parsed.replay = Some(parse_value(&arg, args.next())?),
+            ("headless", Command::Window) => {
+                parsed.command = Command::Headless(HeadlessOptions::default())
+            }
+            ("--list-systems", Command::Window) => parsed.command = Command::ListSystems,
+            (_, Command::Headless(options)) => parse_headless_flag(options, &arg, &mut args)?,
+            (_, Command::Window | Command::ListSystems) => {
+                return Err(format!("unknown argument {:?}\n{}", arg, USAGE))
+            }
+        }
+    }
+    if let (Some(_), Command::Headless(_)) = (&parsed.journal, &parsed.command) {
+        return Err("--journal records window sessions, it can't be used headless".to_string());
+    }
+    // Journal steps count from a freshly built system, which the journal doesn't record
+    if parsed.load.is_some() && (parsed.journal.is_some() || parsed.replay.is_some()) {
+        return Err("--journal and --replay start from a new grid, not from --load".to_string());
+    }
+    Ok(parsed)
+}
+
+#[test]
+fn test_headless_flags() {
+    let args = |text: &str| parse_args(text.split_whitespace().map(str::to_string));
+    match args("headless --steps 5 --npz out.npz --npz-every 2")
+        .unwrap()
+        .command
+    {
+        Command::Headless(options) => assert_eq!((options.steps, options.npz_every), (5, 2)),
+        _ => panic!("expected a headless command"),
+    }
+    // Caught before any output is written
+    assert_eq!(
+        args("headless --npz out.npz --npz-every 0").err(),
+        Some("--npz-every must be at least 1".to_string())
+    );
+}
@@ -1,47 +1,617 @@
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-use serde::Deserialize;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use toml::{Table, Value};
 
-#[derive(Deserialize)]
-pub enum SystemConfig {
-    Conway,
-    XYModel,
-    Wave,
-    Sandpile,
-    Schrodinger,
-}
+use crate::cell::{BoundaryValue, Cell};
+use crate::grid::boundary::{
+    AntiperiodicBoundary, Boundary, ConstantBoundary, Edge, FreeBoundary, KleinBottleBoundary,
+    MixedBoundary, MobiusBoundary, PeriodicBoundary, ReflectingBoundary, TwistedBoundary,
+};
+use crate::grid::grid_pos::GridInt;
+use crate::grid::lattice::Lattice;
+use crate::grid3d::boundary::Boundary3;
+use crate::integrator::Integrator;
+use crate::life_pattern::LifePattern;
+use crate::observables::ObservablesConfig;
+use crate::recording::RecordingConfig;
+use crate::scene::Layer;
+use crate::simulation::{find_system, SYSTEMS};
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub enum BoundaryConfig {
     Periodic,
     Constant,
     Free,
+    Reflecting,
+    // e.g. `boundary = { Twisted = { shift = 10 } }`
+    Twisted { shift: GridInt },
+    // Periodic, with the field changing sign across the edges, for systems which support it
+    Antiperiodic,
+    Mobius,
+    KleinBottle,
+    // e.g. `boundary = { Mixed = { x = "Periodic", top = { Fixed = 1.0 }, bottom = "Free" } }`
+    Mixed(MixedConfig),
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum EdgeConfig {
+    Periodic,
+    Free,
+    Reflecting,
+    // Held at the default (empty) cell
+    Constant,
+    // Held at a cell built from the value, for systems which support it
+    Fixed(f32),
+}
+impl EdgeConfig {
+    fn build<C: Default + BoundaryValue>(self) -> Result<Edge<C>, String> {
+        Ok(match self {
+            EdgeConfig::Periodic => Edge::Periodic,
+            EdgeConfig::Free => Edge::Free,
+            EdgeConfig::Reflecting => Edge::Reflecting,
+            EdgeConfig::Constant => Edge::Constant(C::default()),
+            EdgeConfig::Fixed(value) => Edge::Constant(C::from_value(value).ok_or_else(|| {
+                format!(
+                    "Fixed({}) boundaries are not supported by this system",
+                    value
+                )
+            })?),
+        })
+    }
+}
+
+// Each edge falls back to the setting for its axis.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MixedConfig {
+    x: Option<EdgeConfig>,
+    y: Option<EdgeConfig>,
+    left: Option<EdgeConfig>,
+    right: Option<EdgeConfig>,
+    top: Option<EdgeConfig>,
+    bottom: Option<EdgeConfig>,
+}
+impl MixedConfig {
+    fn build<C: Default + BoundaryValue>(&self) -> Result<MixedBoundary<C>, String> {
+        let edge = |name: &str, edge: Option<EdgeConfig>, axis: Option<EdgeConfig>| {
+            edge.or(axis)
+                .ok_or_else(|| format!("boundary edge `{}` is not set", name))
+        };
+        let left = edge("left", self.left, self.x)?;
+        let right = edge("right", self.right, self.x)?;
+        let top = edge("top", self.top, self.y)?;
+        let bottom = edge("bottom", self.bottom, self.y)?;
+        let periodic = |edge| edge == EdgeConfig::Periodic;
+        if periodic(left) != periodic(right) || periodic(top) != periodic(bottom) {
+            return Err("periodic boundary edges must come in opposite pairs".to_string());
+        }
+        Ok(MixedBoundary {
+            left: left.build()?,
+            right: right.build()?,
+            top: top.build()?,
+            bottom: bottom.build()?,
+        })
+    }
+}
+
+impl BoundaryConfig {
+    /// Whether positions past the x and the y edges wrap around to the other side.
+    fn wraps(&self) -> (bool, bool) {
+        match self {
+            BoundaryConfig::Periodic
+            | BoundaryConfig::Twisted { .. }
+            | BoundaryConfig::Antiperiodic
+            | BoundaryConfig::KleinBottle => (true, true),
+            BoundaryConfig::Mobius => (true, false),
+            BoundaryConfig::Mixed(mixed) => {
+                let periodic = |edge: Option<EdgeConfig>| edge == Some(EdgeConfig::Periodic);
+                (
+                    periodic(mixed.left.or(mixed.x)),
+                    periodic(mixed.top.or(mixed.y)),
+                )
+            }
+            BoundaryConfig::Constant | BoundaryConfig::Free | BoundaryConfig::Reflecting => {
+                (false, false)
+            }
+        }
+    }
+    pub fn build<C: Default + BoundaryValue>(&self) -> Result<Boundary<C>, String> {
+        Ok(match self {
+            BoundaryConfig::Periodic => PeriodicBoundary.into(),
+            BoundaryConfig::Constant => ConstantBoundary::empty().into(),
+            BoundaryConfig::Free => FreeBoundary.into(),
+            BoundaryConfig::Reflecting => ReflectingBoundary.into(),
+            BoundaryConfig::Twisted { shift } => TwistedBoundary { shift: *shift }.into(),
+            BoundaryConfig::Antiperiodic => {
+                if C::default().negate().is_none() {
+                    return Err(
+                        "Antiperiodic boundaries are not supported by this system".to_string()
+                    );
+                }
+                AntiperiodicBoundary::new(|cell: &C| cell.negate().unwrap()).into()
+            }
+            BoundaryConfig::Mobius => MobiusBoundary.into(),
+            BoundaryConfig::KleinBottle => KleinBottleBoundary.into(),
+            BoundaryConfig::Mixed(mixed) => mixed.build()?.into(),
+        })
+    }
+    pub fn build3<C: Default + BoundaryValue>(&self) -> Result<Boundary3<C>, String> {
+        Ok(match self {
+            BoundaryConfig::Periodic => PeriodicBoundary.into(),
+            BoundaryConfig::Constant => ConstantBoundary::empty().into(),
+            BoundaryConfig::Free => FreeBoundary.into(),
+            BoundaryConfig::Reflecting => ReflectingBoundary.into(),
+            BoundaryConfig::Twisted { shift } => TwistedBoundary { shift: *shift }.into(),
+            BoundaryConfig::Antiperiodic => {
+                return Err("Antiperiodic boundaries are only supported in 2D".to_string())
+            }
+            BoundaryConfig::Mobius => MobiusBoundary.into(),
+            BoundaryConfig::KleinBottle => KleinBottleBoundary.into(),
+            BoundaryConfig::Mixed(mixed) => mixed.build()?.into(),
+        })
+    }
+}
+
+// Unknown fields are caught by `check_params`, `deny_unknown_fields` doesn't
+// work together with the flattened `params`
+#[derive(Clone, PartialEq, Deserialize)]
 pub struct Config {
     pub grid_width: u32,
     pub grid_height: u32,
     pub per_frame_updates: u32,
-    pub system: SystemConfig,
+    // One of the names from `--list-systems`
+    #[serde(deserialize_with = "system_name")]
+    pub system: String,
     pub boundary: BoundaryConfig,
+    // Number of z slices. Leave unset for a 2D grid.
+    pub depth: Option<u32>,
+    #[serde(default)]
+    pub lattice: Lattice,
+    // Time stepping for continuous fields in 2D, e.g. Wave and XYModel. Other
+    // systems step with their own scheme, and only accept the default.
+    #[serde(default)]
+    pub integrator: Integrator,
+    // Drives every random number in the run. Chosen at startup (and logged) if unset.
+    pub seed: Option<u64>,
+    // Screenshots are upscaled by this integer factor, e.g. 4 for a 4x4 square per pixel.
+    #[serde(default = "one")]
+    pub screenshot_scale: u32,
+    // Frame range, rate and format of recordings from F7 or `headless --record`
+    #[serde(default)]
+    pub recording: RecordingConfig,
+    // Log measurements like the total energy to a CSV file, in the window and headless
+    pub observables: Option<ObservablesConfig>,
+    // Initial content, as `[[scene]]` layers. The grid starts out random if there are none.
+    #[serde(default)]
+    pub scene: Vec<Layer>,
+    // Model parameters by system section, e.g. `[xy_model] temperature = 0.5`.
+    // Unset values keep their defaults. Without a `rule`, Life-like systems take
+    // it from the scene's pattern files.
+    #[serde(flatten)]
+    pub params: Table,
+}
+impl Config {
+    /// Settings which can only take effect on a freshly built grid.
+    const REBUILD_FIELDS: [&'static str; 7] = [
+        "grid_width",
+        "grid_height",
+        "depth",
+        "lattice",
+        "system",
+        "seed",
+        "scene",
+    ];
+
+    /// Names of the top level settings which differ between `self` and `other`.
+    pub fn changed_fields(&self, other: &Config) -> Vec<&'static str> {
+        let fields = [
+            ("grid_width", self.grid_width != other.grid_width),
+            ("grid_height", self.grid_height != other.grid_height),
+            (
+                "per_frame_updates",
+                self.per_frame_updates != other.per_frame_updates,
+            ),
+            ("system", self.system != other.system),
+            ("boundary", self.boundary != other.boundary),
+            ("depth", self.depth != other.depth),
+            ("lattice", self.lattice != other.lattice),
+            ("integrator", self.integrator != other.integrator),
+            ("seed", self.seed != other.seed),
+            (
+                "screenshot_scale",
+                self.screenshot_scale != other.screenshot_scale,
+            ),
+            ("recording", self.recording != other.recording),
+            ("observables", self.observables != other.observables),
+            ("scene", self.scene != other.scene),
+        ];
+        let sections = SYSTEMS
+            .iter()
+            .filter_map(|entry| entry.params)
+            .map(|section| {
+                let name = section.name;
+                (name, self.params.get(name) != other.params.get(name))
+            });
+        fields
+            .iter()
+            .copied()
+            .chain(sections)
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+    /// Every params section must belong to a system, and hold valid settings for it.
+    fn check_params(&self) -> Result<(), String> {
+        for (name, value) in self.params.iter() {
+            let section = SYSTEMS
+                .iter()
+                .filter_map(|entry| entry.params)
+                .find(|section| section.name == name)
+                .ok_or_else(|| format!("unknown field `{}`", name))?;
+            (section.check)(value).map_err(|e| format!("[{}]: {}", name, e))?;
+        }
+        Ok(())
+    }
+    /// Boundaries which wrap around have to line up the rows of hexagonal
+    /// lattices, and the up and down triangles of triangular ones.
+    fn check_lattice(&self) -> Result<(), String> {
+        if self.lattice == Lattice::Square {
+            return Ok(());
+        }
+        let lattice = format!("{:?} lattices", self.lattice);
+        match self.boundary {
+            BoundaryConfig::Mobius | BoundaryConfig::KleinBottle => {
+                return Err(format!(
+                    "{} can't be flipped by a {:?} boundary",
+                    lattice, self.boundary
+                ))
+            }
+            BoundaryConfig::Twisted { shift } if shift % 2 != 0 => {
+                return Err(format!("{} need an even Twisted shift", lattice))
+            }
+            _ => {}
+        }
+        let (wrap_x, wrap_y) = self.boundary.wraps();
+        if wrap_x && self.lattice == Lattice::Triangular && !self.grid_width.is_multiple_of(2) {
+            return Err(format!(
+                "{} need an even grid_width to wrap around, not {}",
+                lattice, self.grid_width
+            ));
+        }
+        if wrap_y && !self.grid_height.is_multiple_of(2) {
+            return Err(format!(
+                "{} need an even grid_height to wrap around, not {}",
+                lattice, self.grid_height
+            ));
+        }
+        Ok(())
+    }
+    /// The settings in section `name`, empty if there are none.
+    fn section(&self, name: &str) -> Table {
+        match self.params.get(name) {
+            Some(Value::Table(table)) => table.clone(),
+            _ => Table::new(),
+        }
+    }
+    /// The `rule =` headers of the pattern files in the scene.
+    fn pattern_rules(&self) -> Result<Vec<(&Path, String)>, String> {
+        let mut rules = vec![];
+        for layer in self.scene.iter() {
+            if let Layer::Pattern {
+                file: Some(file), ..
+            } = layer
+            {
+                let grid_size = (self.grid_width as usize, self.grid_height as usize);
+                if let Some(rule) = LifePattern::load(file, grid_size)?.rule {
+                    rules.push((file.as_path(), rule));
+                }
+            }
+        }
+        Ok(rules)
+    }
+    /// Whether switching to `other` needs a new simulation, rather than `Simulation::reconfigure`.
+    pub fn needs_rebuild(&self, other: &Config) -> bool {
+        self.changed_fields(other)
+            .iter()
+            .any(|field| Self::REBUILD_FIELDS.contains(field))
+    }
+}
+
+// Sets the rule from the header of a pattern `file`, unless the section already
+// has one. Headers which don't parse are only an error if they would be used.
+fn pattern_rule<C>(table: &mut Table, file: &Path, header: &str) -> Result<(), String>
+where
+    C: Cell,
+    C::Params: DeserializeOwned,
+{
+    // Golly adds the grid's topology after a colon, e.g. `B3/S23:T100,100`
+    let header = header.split(':').next().unwrap_or_default().trim();
+    let mut with_header = table.clone();
+    with_header.insert("rule".to_string(), Value::String(header.to_string()));
+    let rule = parse_section::<C::Params>(Value::Table(with_header.clone()));
+    if !table.contains_key("rule") {
+        rule.map_err(|e| format!("pattern {}: {}", file.display(), e))?;
+        *table = with_header;
+        return Ok(());
+    }
+    let configured =
+        C::rulestring(&parse_section(Value::Table(table.clone()))?).unwrap_or_default();
+    match rule.map(|rule| C::rulestring(&rule).unwrap_or_default()) {
+        Ok(rule) if rule == configured => {}
+        Ok(rule) => warn!(
+            "{} is for rule {}, but will be run as {}",
+            file.display(),
+            rule,
+            configured
+        ),
+        Err(e) => warn!(
+            "{} has a rule header which doesn't parse ({}), running it as {}",
+            file.display(),
+            e,
+            configured
+        ),
+    }
+    Ok(())
+}
+
+/// The params in a section of the config.
+pub fn parse_section<P: DeserializeOwned>(section: Value) -> Result<P, String> {
+    // Keep the path to the bad value, e.g. "in `rule`", on the same line
+    section
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string().trim_end().replace('\n', ", "))
+}
+
+fn one() -> u32 {
+    1
 }
-impl Default for Config {
-    fn default() -> Self {
+
+fn system_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    find_system(&name).map_err(de::Error::custom)?;
+    Ok(name)
+}
+
+/// Cells whose `Params` come from the section of their system in the config.
+pub trait ConfigParams: Cell {
+    fn params(config: &Config) -> Result<Self::Params, String>;
+}
+impl<C> ConfigParams for C
+where
+    C: Cell,
+    C::Params: DeserializeOwned,
+{
+    fn params(config: &Config) -> Result<C::Params, String> {
+        let name = match find_system(&config.system)?.params {
+            Some(section) => section.name,
+            None => return Ok(C::Params::default()),
+        };
+        let mut table = config.section(name);
+        if C::rulestring(&C::Params::default()).is_some() {
+            for (file, header) in config.pattern_rules()? {
+                pattern_rule::<C>(&mut table, file, &header)?;
+            }
+        }
+        parse_section(Value::Table(table))
+            .and_then(|params| C::on_lattice(params, config.lattice))
+            .map_err(|e| format!("[{}]: {}", name, e))
+    }
+}
+
+pub fn load_config(path: &Path, overrides: &[(String, String)]) -> Result<Config, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read config {}: {}", path.display(), e))?;
+    parse_config(&text, overrides)
+        .map_err(|msg| format!("invalid config {}: {}", path.display(), msg))
+}
+
+fn parse_config(text: &str, overrides: &[(String, String)]) -> Result<Config, String> {
+    // Errors from the file text carry the line and column, which are lost once
+    // the overrides have been merged in
+    let from_file = toml::from_str::<Config>(text);
+    if overrides.is_empty() {
+        let config = from_file.map_err(|e| e.to_string())?;
+        config.check_params()?;
+        config.check_lattice()?;
+        return Ok(config);
+    }
+    let mut table: Table = toml::from_str(text).map_err(|e: toml::de::Error| e.to_string())?;
+    for (key, value) in overrides {
+        set_override(&mut table, key, value)?;
+    }
+    let config = Config::deserialize(Value::Table(table)).map_err(|e| match from_file {
+        Err(file_error) if file_error.message() == e.message() => file_error.to_string(),
+        _ => format!("after --set overrides: {}", e),
+    })?;
+    config.check_params()?;
+    config.check_lattice()?;
+    Ok(config)
+}
+
+/// Re-reads a config file, with the same overrides, whenever it is modified.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    overrides: Vec<(String, String)>,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+impl ConfigWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(path: PathBuf, overrides: Vec<(String, String)>) -> Self {
         Self {
-            grid_width: 200,
-            grid_height: 200,
-            per_frame_updates: 1,
-            system: SystemConfig::XYModel,
-            boundary: BoundaryConfig::Periodic,
+            modified: Self::modified_time(&path),
+            path,
+            overrides,
+            last_check: Instant::now(),
         }
     }
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+    /// The new config if the file changed since the last call. The file is only
+    /// checked every `POLL_INTERVAL`, so this is cheap to call every frame.
+    pub fn poll(&mut self) -> Option<Result<Config, String>> {
+        if self.last_check.elapsed() < Self::POLL_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        let modified = Self::modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(load_config(&self.path, &self.overrides))
+    }
 }
 
-pub fn read_config() -> Config {
-    fs::read_to_string("config.toml")
-        .map_err(|e| e.to_string())
-        .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()))
-        .map_err(|msg| println!("{:}", msg))
-        .unwrap_or_default()
+// `key` may be dotted to reach into a section. Values which aren't valid TOML
+// are taken as strings, so `--set system=Wave` doesn't need quotes.
+fn set_override(table: &mut Table, key: &str, value: &str) -> Result<(), String> {
+    let value = toml::from_str::<Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()));
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut section = table;
+    for part in parts {
+        section = section
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("--set {}: `{}` is not a section", key, part))?;
+    }
+    section.insert(last.to_string(), value);
+    Ok(())
+}
+
+#[test]
+fn test_config_errors_and_overrides() {
+    let text = "grid_width = 20\ngrid_height = 10\nper_frame_updates = 1\n\
+        system = \"XYModle\"\nboundary = \"Periodic\"\n";
+    let error = parse_config(text, &[]).err().unwrap();
+    assert!(error.contains("line 4"), "{}", error);
+    assert!(error.contains("XYModel"), "{}", error);
+
+    let set = |key: &str, value: &str| (key.to_string(), value.to_string());
+    let config = parse_config(
+        text,
+        &[set("system", "XYModel"), set("xy_model.temperature", "0.5")],
+    )
+    .unwrap();
+    assert_eq!(config.system, "XYModel");
+    let params = crate::cell_library::XYModelCell::params(&config).unwrap();
+    assert_eq!(params.temperature, 0.5);
+
+    let error = parse_config(text, &[set("system", "Wave"), set("widht", "3")])
+        .err()
+        .unwrap();
+    assert!(error.contains("widht"), "{}", error);
+
+    let error = parse_config(text, &[set("system", "Wave"), set("wave.dampnig", "3")])
+        .err()
+        .unwrap();
+    assert!(
+        error.contains("[wave]") && error.contains("dampnig"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_odd_lattices_dont_wrap() {
+    let config = |lattice: &str, width: u32, height: u32, boundary: &str| {
+        let text = format!(
+            "grid_width = {}\ngrid_height = {}\nper_frame_updates = 1\n\
+            system = \"Conway\"\nlattice = \"{}\"\nboundary = {}\n",
+            width, height, lattice, boundary
+        );
+        parse_config(&text, &[])
+    };
+    let periodic = "\"Periodic\"";
+    assert!(config("Hexagonal", 9, 8, periodic).is_ok());
+    assert!(config("Triangular", 10, 8, periodic).is_ok());
+    let error = config("Hexagonal", 10, 7, periodic).err().unwrap();
+    assert!(error.contains("even grid_height"), "{}", error);
+    let error = config("Triangular", 9, 8, periodic).err().unwrap();
+    assert!(error.contains("even grid_width"), "{}", error);
+    assert!(config("Triangular", 10, 9, periodic).is_err());
+    assert!(config("Hexagonal", 9, 8, "{ Twisted = { shift = 3 } }").is_err());
+    assert!(config("Triangular", 10, 8, "\"KleinBottle\"").is_err());
+
+    // Only the edges which wrap around matter
+    assert!(config("Hexagonal", 9, 7, "\"Reflecting\"").is_ok());
+    assert!(config("Triangular", 9, 7, "\"Free\"").is_ok());
+    let rows_only = "{ Mixed = { x = \"Periodic\", y = \"Free\" } }";
+    assert!(config("Hexagonal", 9, 7, rows_only).is_ok());
+    assert!(config("Triangular", 9, 8, rows_only).is_err());
+}
+
+#[test]
+fn test_pattern_rule_headers() {
+    use crate::cell_library::ConwayCell;
+
+    let file = Path::new("pattern.rle");
+    let rule = |table: &Table| {
+        table
+            .get("rule")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let mut table = Table::new();
+    pattern_rule::<ConwayCell>(&mut table, file, "B36/S23:T100,100").unwrap();
+    assert_eq!(rule(&table).unwrap(), "B36/S23");
+    assert!(pattern_rule::<ConwayCell>(&mut Table::new(), file, "LifeHistory").is_err());
+
+    // A configured rule wins, even over headers which don't parse
+    for header in ["B3/S23", "LifeHistory"].iter() {
+        pattern_rule::<ConwayCell>(&mut table, file, header).unwrap();
+        assert_eq!(rule(&table).unwrap(), "B36/S23");
+    }
+}
+
+#[test]
+fn test_logged_seeds_parse() {
+    // Seeds chosen at startup are logged, to be pasted back into the config
+    let seeds = (0..64).map(|_| crate::util::entropy_seed());
+    for seed in seeds.chain(Some(i64::MAX as u64)) {
+        let text = format!(
+            "grid_width = 20\ngrid_height = 10\nper_frame_updates = 1\n\
+            system = \"Wave\"\nboundary = \"Periodic\"\nseed = {}\n",
+            seed
+        );
+        assert_eq!(parse_config(&text, &[]).unwrap().seed, Some(seed));
+        let set = [("seed".to_string(), seed.to_string())];
+        assert_eq!(parse_config(&text, &set).unwrap().seed, Some(seed));
+    }
+}
+
+#[test]
+fn test_config_changes() {
+    let text = "grid_width = 20\ngrid_height = 10\nper_frame_updates = 1\n\
+        system = \"Wave\"\nboundary = \"Periodic\"\n";
+    let config = |overrides: &[(&str, &str)]| {
+        let overrides: Vec<_> = overrides
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        parse_config(text, &overrides).unwrap()
+    };
+    let base = config(&[]);
+    assert!(base.changed_fields(&config(&[])).is_empty());
+
+    let live = config(&[("wave.damping", "0.5"), ("boundary", "Free")]);
+    assert_eq!(base.changed_fields(&live), ["boundary", "wave"]);
+    assert!(!base.needs_rebuild(&live));
+
+    let resized = config(&[("grid_width", "30")]);
+    assert_eq!(base.changed_fields(&resized), ["grid_width"]);
+    assert!(base.needs_rebuild(&resized));
 }
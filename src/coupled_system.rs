@@ -0,0 +1,330 @@
+use std::any::Any;
+
+use log::info;
+use winit::event::VirtualKeyCode;
+
+use crate::cell::{BoundaryValue, CoupledCell, HasColor, Randomize};
+use crate::config::{Config, ConfigParams};
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::grid_view::GridView;
+use crate::grid::lattice::Lattice;
+use crate::grid::Grid;
+use crate::npy::FieldData;
+use crate::simulation::{check_own_integrator, Simulation};
+use crate::snapshot::{decode_cells, encode_cells};
+
+/// Read access to all fields of a `CoupledSystem` during an update.
+pub struct Fields<'a> {
+    fields: &'a [Box<dyn Field>],
+}
+impl<'a> Fields<'a> {
+    /// A view into the field called `name`, centered on `origin`.
+    pub fn view<C: CoupledCell + 'static>(
+        &self,
+        name: &str,
+        origin: GridPos,
+    ) -> Option<GridView<'a, C>> {
+        self.named_grid(name)
+            .map(|named| GridView::new(origin, &named.grid, &named.params))
+    }
+    /// The field called `name`, if it exists and holds cells of type `C`.
+    fn named_grid<C: CoupledCell + 'static>(&self, name: &str) -> Option<&'a NamedGrid<C>> {
+        self.fields
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(|field| field.as_any().downcast_ref::<NamedGrid<C>>())
+    }
+}
+
+struct NamedGrid<C: CoupledCell> {
+    name: String,
+    grid: Grid<C>,
+    params: C::Params,
+}
+
+// Object safe interface to a `NamedGrid<C>`, so fields of different cell
+// types can be stored together.
+trait Field {
+    fn name(&self) -> &str;
+    fn as_any(&self) -> &dyn Any;
+    fn take_scratch(&mut self) -> Box<dyn Any>;
+    fn fill_scratch(&self, scratch: &mut dyn Any, fields: &Fields);
+    fn finish_update(&mut self, scratch: Box<dyn Any>);
+    fn color_at(&self, idx: usize) -> [u8; 4];
+    fn toggle(&mut self, target_pos: &GridPos);
+    fn randomize(&mut self);
+    fn clear(&mut self);
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String>;
+    fn save_cells(&self) -> Result<Vec<u8>, String>;
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String>;
+    fn field_data(&self) -> Result<FieldData, String>;
+    fn observables(&self) -> Vec<(&'static str, f64)>;
+}
+impl<C> Field for NamedGrid<C>
+where
+    C: CoupledCell + ConfigParams + Default + HasColor + Randomize + BoundaryValue + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn take_scratch(&mut self) -> Box<dyn Any> {
+        Box::new(std::mem::take(&mut self.grid.scratch_cells))
+    }
+    fn fill_scratch(&self, scratch: &mut dyn Any, fields: &Fields) {
+        let scratch = scratch.downcast_mut::<Vec<C>>().unwrap();
+        for grid_pos in self.grid.get_grid_pos_iter() {
+            let grid_view = GridView::new(grid_pos, &self.grid, &self.params);
+            let cell = self.grid.get_cell_at(grid_pos);
+            scratch[self.grid.to_idx(&grid_pos)] = cell.update_coupled(grid_view, fields);
+        }
+    }
+    fn finish_update(&mut self, scratch: Box<dyn Any>) {
+        self.grid.scratch_cells = *scratch.downcast::<Vec<C>>().unwrap();
+        self.grid.swap();
+        if C::thermalize(&mut self.grid.cells, &self.params) {
+            self.grid.refresh_boundary();
+        }
+    }
+    fn color_at(&self, idx: usize) -> [u8; 4] {
+        self.grid.cells[idx].draw()
+    }
+    fn toggle(&mut self, target_pos: &GridPos) {
+        for grid_pos in self.grid.get_grid_pos_iter() {
+            let idx = self.grid.to_idx(&grid_pos);
+            self.grid.cells[idx].toggle(target_pos, &grid_pos)
+        }
+    }
+    fn randomize(&mut self) {
+        self.grid.randomize(&self.params)
+    }
+    fn clear(&mut self) {
+        self.grid.clear()
+    }
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String> {
+        self.grid.set_boundary(config.boundary.build()?);
+        self.params = C::params(config)?;
+        Ok(())
+    }
+    fn save_cells(&self) -> Result<Vec<u8>, String> {
+        encode_cells(&self.grid.cells)
+    }
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String> {
+        decode_cells(data, &mut self.grid.cells)
+    }
+    fn field_data(&self) -> Result<FieldData, String> {
+        FieldData::from_cells(&self.grid.cells, vec![self.grid.height, self.grid.width])
+    }
+    fn observables(&self) -> Vec<(&'static str, f64)> {
+        C::observables(&self.grid, &self.params)
+    }
+}
+
+/// Which field(s) a `CoupledSystem` draws.
+#[derive(Clone, Copy, Debug)]
+pub enum FieldDisplay {
+    Field(usize),
+    /// Sum of the colors of all fields.
+    Composite,
+}
+
+/// Several grids of the same size, possibly of different cell types, updated
+/// in lockstep. Every cell update can read any field at the same position
+/// through `Fields`, and sees the state from before the step.
+pub struct CoupledSystem {
+    fields: Vec<Box<dyn Field>>,
+    width: usize,
+    height: usize,
+    lattice: Lattice,
+    pub display: FieldDisplay,
+}
+impl CoupledSystem {
+    pub fn new(width: usize, height: usize, lattice: Lattice) -> Self {
+        Self {
+            fields: vec![],
+            width,
+            height,
+            lattice,
+            display: FieldDisplay::Composite,
+        }
+    }
+    pub fn with_field<C>(mut self, name: &str, grid: Grid<C>, params: C::Params) -> Self
+    where
+        C: CoupledCell + ConfigParams + Default + HasColor + Randomize + BoundaryValue + 'static,
+    {
+        assert_eq!((grid.width, grid.height), (self.width, self.height));
+        assert_eq!(grid.lattice, self.lattice);
+        self.fields.push(Box::new(NamedGrid {
+            name: name.to_string(),
+            grid,
+            params,
+        }));
+        self
+    }
+    fn displayed_fields(&mut self) -> Vec<&mut Box<dyn Field>> {
+        match self.display {
+            FieldDisplay::Field(i) => self.fields.iter_mut().skip(i).take(1).collect(),
+            FieldDisplay::Composite => self.fields.iter_mut().collect(),
+        }
+    }
+}
+impl Simulation for CoupledSystem {
+    fn update(&mut self) {
+        let mut scratches: Vec<_> = self.fields.iter_mut().map(|f| f.take_scratch()).collect();
+        let fields = Fields {
+            fields: &self.fields,
+        };
+        for (field, scratch) in self.fields.iter().zip(scratches.iter_mut()) {
+            field.fill_scratch(scratch.as_mut(), &fields);
+        }
+        for (field, scratch) in self.fields.iter_mut().zip(scratches) {
+            field.finish_update(scratch);
+        }
+    }
+    fn frame_size(&self) -> (usize, usize) {
+        self.lattice.frame_size(self.width, self.height)
+    }
+    fn draw(&self, frame: &mut [u8]) {
+        let (frame_width, frame_height) = self.frame_size();
+        debug_assert_eq!(frame.len(), 4 * frame_width * frame_height);
+        let shown: Vec<_> = match self.display {
+            FieldDisplay::Field(i) => self.fields.iter().skip(i).take(1).collect(),
+            FieldDisplay::Composite => self.fields.iter().collect(),
+        };
+        // Not every pixel is covered by a cell on non-square lattices
+        frame.iter_mut().for_each(|byte| *byte = 0);
+        for idx in 0..self.width * self.height {
+            let mut color = [0u8; 4];
+            for field in shown.iter() {
+                for (channel, value) in color.iter_mut().zip(field.color_at(idx).iter()) {
+                    *channel = channel.saturating_add(*value);
+                }
+            }
+            let grid_pos =
+                GridPos::new((idx % self.width) as GridInt, (idx / self.width) as GridInt);
+            for (px, py) in self.lattice.cell_pixels(grid_pos) {
+                let pix = 4 * (px + py * frame_width);
+                frame[pix..pix + 4].copy_from_slice(&color);
+            }
+        }
+    }
+    fn pixel_to_cell(&self, px: isize, py: isize) -> (isize, isize) {
+        self.lattice.pixel_to_cell(px, py)
+    }
+    fn toggle(&mut self, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return false;
+        }
+        let target_pos = GridPos::new(x as GridInt, y as GridInt);
+        for field in self.displayed_fields() {
+            field.toggle(&target_pos)
+        }
+        true
+    }
+    fn randomize(&mut self) {
+        self.fields.iter_mut().for_each(|field| field.randomize())
+    }
+    fn clear(&mut self) {
+        self.fields.iter_mut().for_each(|field| field.clear())
+    }
+    fn key_response(&mut self, vkc: VirtualKeyCode) {
+        let keys = [
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+            VirtualKeyCode::Key9,
+        ];
+        if vkc == VirtualKeyCode::Key0 {
+            self.display = FieldDisplay::Composite;
+            info!("Showing all fields");
+        } else if let Some(i) = keys.iter().position(|&k| k == vkc) {
+            if let Some(field) = self.fields.get(i) {
+                info!("Showing field {}", field.name());
+                self.display = FieldDisplay::Field(i);
+            }
+        }
+    }
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String> {
+        check_own_integrator(config)?;
+        self.fields
+            .iter_mut()
+            .try_for_each(|field| field.reconfigure(config))
+    }
+    fn save_cells(&self) -> Result<Vec<u8>, String> {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| field.save_cells())
+            .collect::<Result<Vec<_>, _>>()?;
+        encode_cells(&fields)
+    }
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut fields = vec![vec![]; self.fields.len()];
+        decode_cells(data, &mut fields)?;
+        self.fields
+            .iter_mut()
+            .zip(fields)
+            .try_for_each(|(field, data)| field.load_cells(&data))
+    }
+    // Named `<field>.<name>`, e.g. `prey.mass`
+    fn observables(&self) -> Vec<(String, f64)> {
+        self.fields
+            .iter()
+            .flat_map(|field| {
+                let observables = field.observables();
+                observables
+                    .into_iter()
+                    .map(move |(name, value)| (format!("{}.{}", field.name(), name), value))
+            })
+            .collect()
+    }
+    // Named `<field>.<name>`, e.g. `prey.density`
+    fn field_data(&self) -> Result<FieldData, String> {
+        let mut fields = vec![];
+        for field in self.fields.iter() {
+            let data = field.field_data()?;
+            fields.extend(
+                data.fields
+                    .into_iter()
+                    .map(|(name, values)| (format!("{}.{}", field.name(), name), values)),
+            );
+        }
+        Ok(FieldData {
+            shape: vec![self.height, self.width],
+            fields,
+        })
+    }
+}
+
+#[test]
+fn test_fields_lookup_by_name_and_type() {
+    use crate::cell_library::{PredatorCell, PreyCell};
+    use crate::grid::boundary::PeriodicBoundary;
+
+    let system = CoupledSystem::new(4, 3, Lattice::Square)
+        .with_field(
+            "prey",
+            Grid::<PreyCell>::new_empty(4, 3, PeriodicBoundary.into()),
+            Default::default(),
+        )
+        .with_field(
+            "predator",
+            Grid::<PredatorCell>::new_empty(4, 3, PeriodicBoundary.into()),
+            Default::default(),
+        );
+    let fields = Fields {
+        fields: &system.fields,
+    };
+    let origin = GridPos::default();
+    assert!(fields.view::<PreyCell>("prey", origin).is_some());
+    assert!(fields.view::<PredatorCell>("predator", origin).is_some());
+    assert!(fields.view::<PredatorCell>("prey", origin).is_none());
+    assert!(fields.view::<PreyCell>("wolves", origin).is_none());
+}
@@ -1,22 +1,30 @@
-use crate::cell::{Cell, System};
+use crate::cell::{Cell, Cell3, System};
 use crate::grid::grid_pos::{GridInt, GridPos};
 use crate::grid::grid_view::GridView;
 use crate::grid::Grid;
+use crate::grid3d::grid_pos::GridPos3;
+use crate::grid3d::{Grid3, GridView3, Projection};
 
 pub struct GenericSystemData(pub i32);
 
-pub struct GenericSystem<C> {
+pub struct GenericSystem<C: Cell> {
     pub grid: Grid<C>,
+    pub params: C::Params,
 }
-impl<C> GenericSystem<C> {
+impl<C: Cell> GenericSystem<C> {
     pub fn new(grid: Grid<C>) -> Self {
-        Self { grid }
+        Self {
+            grid,
+            params: C::Params::default(),
+        }
     }
-}
-impl<C: Cell> System<C> for GenericSystem<C> {
-    fn update(&mut self) {
+    pub fn with_params(mut self, params: C::Params) -> Self {
+        self.params = params;
+        self
+    }
+    pub fn update_serial(&mut self) {
         for grid_pos in self.grid.get_grid_pos_iter() {
-            let grid_view = GridView::new(grid_pos, &self.grid);
+            let grid_view = GridView::new(grid_pos, &self.grid, &self.params);
             let cell = self.grid.get_cell_at(grid_pos);
             let next = self.update_cell(grid_view, cell);
             // Write into scratch_cells, since we're still reading from `self.cells`
@@ -24,6 +32,47 @@ impl<C: Cell> System<C> for GenericSystem<C> {
         }
         self.grid.swap()
     }
+    // Each row of `scratch_cells` is filled by a separate rayon task. Cells only
+    // read from `cells`, so this gives the same result as `update_serial`.
+    #[cfg(feature = "parallel")]
+    pub fn update_parallel(&mut self) {
+        use rayon::prelude::*;
+
+        let mut scratch_cells = std::mem::take(&mut self.grid.scratch_cells);
+        let this = &*self;
+        scratch_cells
+            .par_chunks_mut(this.grid.width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, slot) in row.iter_mut().enumerate() {
+                    let grid_pos = GridPos::new(x as GridInt, y as GridInt);
+                    let grid_view = GridView::new(grid_pos, &this.grid, &this.params);
+                    *slot = this.update_cell(grid_view, this.grid.get_cell_at(grid_pos));
+                }
+            });
+        self.grid.scratch_cells = scratch_cells;
+        self.grid.swap()
+    }
+}
+impl<C: Cell> System<C> for GenericSystem<C> {
+    fn update(&mut self) {
+        // Cells may have been edited since the last update, e.g. toggled
+        self.grid.refresh_boundary();
+        let mut next = std::mem::take(&mut self.grid.scratch_cells);
+        let updated = C::update_grid(&self.grid, &self.params, &mut next);
+        self.grid.scratch_cells = next;
+        if updated {
+            self.grid.swap();
+        } else {
+            #[cfg(feature = "parallel")]
+            self.update_parallel();
+            #[cfg(not(feature = "parallel"))]
+            self.update_serial();
+        }
+        if C::thermalize(&mut self.grid.cells, &self.params) {
+            self.grid.refresh_boundary();
+        }
+    }
     fn update_cell(&self, grid_view: GridView<C>, cell: &C) -> C {
         cell.update(grid_view)
     }
@@ -48,3 +97,118 @@ impl<C: Cell> System<C> for GenericSystem<C> {
         }
     }
 }
+
+pub struct GenericSystem3<C: Cell3> {
+    pub grid: Grid3<C>,
+    pub projection: Projection,
+    pub params: C::Params,
+}
+impl<C: Cell3> GenericSystem3<C> {
+    pub fn new(grid: Grid3<C>) -> Self {
+        let projection = Projection::Slice(grid.depth / 2);
+        Self {
+            grid,
+            projection,
+            params: C::Params::default(),
+        }
+    }
+    pub fn with_params(mut self, params: C::Params) -> Self {
+        self.params = params;
+        self
+    }
+    /// The slice that mouse input acts on. Max projections act on the middle slice.
+    pub fn active_slice(&self) -> usize {
+        match self.projection {
+            Projection::Slice(z) => z,
+            Projection::Max => self.grid.depth / 2,
+        }
+    }
+    pub fn move_slice(&mut self, dz: isize) {
+        let z = self.active_slice() as isize + dz;
+        self.projection = Projection::Slice(z.clamp(0, self.grid.depth as isize - 1) as usize);
+    }
+    pub fn toggle_max_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Slice(_) => Projection::Max,
+            Projection::Max => Projection::Slice(self.active_slice()),
+        }
+    }
+    fn update_grid_pos(&self, grid_pos: GridPos3) -> C {
+        let grid_view = GridView3::new(grid_pos, &self.grid, &self.params);
+        self.grid.get_cell_at(grid_pos).update_3d(grid_view)
+    }
+    pub fn update(&mut self) {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            let mut scratch_cells = std::mem::take(&mut self.grid.scratch_cells);
+            let (width, height) = (self.grid.width, self.grid.height);
+            let this = &*self;
+            scratch_cells
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(|(row, cells)| {
+                    let (y, z) = ((row % height) as GridInt, (row / height) as GridInt);
+                    for (x, slot) in cells.iter_mut().enumerate() {
+                        *slot = this.update_grid_pos(GridPos3::new(x as GridInt, y, z));
+                    }
+                });
+            self.grid.scratch_cells = scratch_cells;
+        }
+        #[cfg(not(feature = "parallel"))]
+        for grid_pos in self.grid.get_grid_pos_iter() {
+            let next = self.update_grid_pos(grid_pos);
+            self.grid.set_scatch_cell_at(grid_pos, next);
+        }
+        self.grid.swap();
+        C::thermalize(&mut self.grid.cells, &self.params);
+    }
+    /// Toggle the cells of the active slice around `(x, y)`.
+    pub fn toggle(&mut self, x: isize, y: isize) -> bool {
+        let z = self.active_slice();
+        match self.grid.grid_idx(x, y, z as isize) {
+            Some(_i) => {
+                let target_pos = GridPos::new(x as GridInt, y as GridInt);
+                let area = self.grid.width * self.grid.height;
+                let slice = &mut self.grid.cells[z * area..(z + 1) * area];
+                for (idx, cell) in slice.iter_mut().enumerate() {
+                    let grid_pos = GridPos::new(
+                        (idx % self.grid.width) as GridInt,
+                        (idx / self.grid.width) as GridInt,
+                    );
+                    cell.toggle(&target_pos, &grid_pos)
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_matches_serial() {
+    use crate::cell::Randomize;
+    use crate::cell_library::{ConwayCell, WaveCell};
+    use crate::grid::boundary::PeriodicBoundary;
+
+    fn check<C: Cell + Default + Randomize + std::fmt::Debug>() {
+        let mut grid = Grid::new_empty(37, 23, PeriodicBoundary.into());
+        grid.randomize(&C::Params::default());
+        let mut serial = GenericSystem::<C>::new(grid);
+        let mut parallel = GenericSystem::new(Grid::new_empty(37, 23, PeriodicBoundary.into()));
+        parallel.grid.cells = serial.grid.cells.clone();
+        for _ in 0..10 {
+            serial.update_serial();
+            parallel.update_parallel();
+        }
+        // Debug output round-trips floats exactly, so this compares bit-for-bit
+        assert_eq!(
+            format!("{:?}", serial.grid.cells),
+            format!("{:?}", parallel.grid.cells)
+        );
+    }
+    check::<ConwayCell>();
+    check::<WaveCell>();
+}
@@ -3,11 +3,15 @@ pub use grid_view::GridView;
 use crate::cell::{HasColor, Randomize};
 use crate::grid::boundary::{Boundary, BoundaryTrait};
 use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::lattice::Lattice;
 use crate::util::generate_seed;
 
 pub mod boundary;
 pub mod grid_pos;
 pub mod grid_view;
+pub mod lattice;
+pub mod neighborhood;
+pub mod summed_area;
 
 #[derive(Debug)]
 pub struct Grid<C> {
@@ -15,6 +19,7 @@ pub struct Grid<C> {
     pub width: usize,
     pub height: usize,
     boundary: Boundary<C>,
+    pub lattice: Lattice,
     // Should always be the same size as `cells`. When updating, we read from
     // `cells` and write to `scratch_cells`, then swap. Otherwise it's not in
     // use, and `cells` should be updated directly.
@@ -23,6 +28,11 @@ pub struct Grid<C> {
 impl<C> Grid<C> {
     pub fn swap(&mut self) {
         std::mem::swap(&mut self.scratch_cells, &mut self.cells);
+        self.refresh_boundary();
+    }
+    /// Let the boundary catch up with changes to `cells`, before reading past the edges.
+    pub fn refresh_boundary(&mut self) {
+        self.boundary.refresh(&self.cells);
     }
     // todo make private
     pub fn to_idx(&self, grid_pos: &GridPos) -> usize {
@@ -49,6 +59,18 @@ impl<C> Grid<C> {
             None
         }
     }
+    pub fn set_boundary(&mut self, boundary: Boundary<C>) {
+        self.boundary = boundary;
+        self.refresh_boundary();
+    }
+    pub fn with_lattice(mut self, lattice: Lattice) -> Self {
+        self.lattice = lattice;
+        self
+    }
+    /// Size in pixels of the buffer passed to `draw`.
+    pub fn frame_size(&self) -> (usize, usize) {
+        self.lattice.frame_size(self.width, self.height)
+    }
     pub fn raw_get_cell_at(&self, grid_pos: &GridPos) -> Option<&C> {
         self.grid_idx(grid_pos.x as isize, grid_pos.y as isize)
             .map(|idx| &self.cells[idx])
@@ -58,43 +80,53 @@ impl<C: Clone + Default> Grid<C> {
     pub fn new_empty(width: usize, height: usize, boundary: Boundary<C>) -> Self {
         assert!(width != 0 && height != 0);
         let size = width.checked_mul(height).expect("too big");
-        Self {
+        let mut grid = Self {
             cells: vec![C::default(); size],
             scratch_cells: vec![C::default(); size],
             width,
             height,
             boundary,
-        }
+            lattice: Lattice::default(),
+        };
+        grid.refresh_boundary();
+        grid
     }
 }
-impl<C: Default> Grid<C> {
+impl<'a, C: Clone + Default + Randomize> Grid<C> {
     pub fn clear(&mut self) {
-        for c in self.cells.iter_mut() {
-            *c = C::default();
+        let mut rng: randomize::PCG32 = generate_seed().into();
+        for grid_pos in self.get_grid_pos_iter() {
+            let idx = self.to_idx(&grid_pos);
+            self.cells[idx] = C::empty(&mut rng, grid_pos);
         }
     }
-}
-
-impl<'a, C: Clone + Default + Randomize> Grid<C> {
-    pub fn randomize(&mut self) {
+    pub fn randomize(&mut self, params: &C::Params) {
         let mut rng: randomize::PCG32 = generate_seed().into();
         for grid_pos in self.get_grid_pos_iter() {
             let idx = self.to_idx(&grid_pos);
-            self.cells[idx] = C::random(&mut rng, grid_pos);
+            self.cells[idx] = C::random(&mut rng, grid_pos, params);
         }
     }
-    pub fn new_random(width: usize, height: usize, boundary: Boundary<C>) -> Self {
-        let mut result = Self::new_empty(width, height, boundary);
-        result.randomize();
-        result
-    }
 }
 
 impl<'a, C: HasColor> Grid<C> {
     pub fn draw(&self, screen: &mut [u8]) {
-        debug_assert_eq!(screen.len(), 4 * self.cells.len());
-        for (c, pix) in self.cells.iter().zip(screen.chunks_exact_mut(4)) {
-            pix.copy_from_slice(&c.draw());
+        let (frame_width, frame_height) = self.frame_size();
+        debug_assert_eq!(screen.len(), 4 * frame_width * frame_height);
+        if self.lattice == Lattice::Square {
+            for (c, pix) in self.cells.iter().zip(screen.chunks_exact_mut(4)) {
+                pix.copy_from_slice(&c.draw());
+            }
+            return;
+        }
+        // Not every pixel is covered by a cell
+        screen.iter_mut().for_each(|byte| *byte = 0);
+        for grid_pos in self.get_grid_pos_iter() {
+            let color = self.cells[self.to_idx(&grid_pos)].draw();
+            for (px, py) in self.lattice.cell_pixels(grid_pos) {
+                let idx = 4 * (px + py * frame_width);
+                screen[idx..idx + 4].copy_from_slice(&color);
+            }
         }
     }
 }
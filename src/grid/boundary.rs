@@ -6,6 +6,8 @@ use enum_dispatch::enum_dispatch;
 #[enum_dispatch]
 pub trait BoundaryTrait<C> {
     fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C;
+    /// Bring any cells derived from the grid's up to date, after `cells` changed.
+    fn refresh(&mut self, _cells: &[C]) {}
 }
 
 #[derive(Debug)]
@@ -45,10 +47,251 @@ impl<C> BoundaryTrait<C> for FreeBoundary {
     }
 }
 
+/// Mirror at the outermost cells, which are not repeated: -1 maps to 1.
+/// This makes the field symmetric about the edge, like a Neumann boundary.
+pub(crate) fn reflect(x: GridInt, n: GridInt) -> GridInt {
+    if n == 1 {
+        return 0;
+    }
+    let period = 2 * (n - 1);
+    let x = modulo(x, period);
+    if x < n {
+        x
+    } else {
+        period - x
+    }
+}
+
+#[derive(Debug)]
+pub struct ReflectingBoundary;
+impl<C> BoundaryTrait<C> for ReflectingBoundary {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        let new_grid_pos = GridPos::new(
+            reflect(grid_pos.x, grid.width as GridInt),
+            reflect(grid_pos.y, grid.height as GridInt),
+        );
+        grid.raw_get_cell_at(&new_grid_pos).unwrap()
+    }
+}
+
+/// Periodic, but each wrap around in x also shifts y by `shift` (a helical boundary).
+#[derive(Debug)]
+pub struct TwistedBoundary {
+    pub shift: GridInt,
+}
+impl TwistedBoundary {
+    pub(crate) fn map_xy(
+        &self,
+        x: GridInt,
+        y: GridInt,
+        width: GridInt,
+        height: GridInt,
+    ) -> (GridInt, GridInt) {
+        let wraps = x.div_euclid(width);
+        (modulo(x, width), modulo(y + wraps * self.shift, height))
+    }
+}
+impl<C> BoundaryTrait<C> for TwistedBoundary {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        let (x, y) = self.map_xy(
+            grid_pos.x,
+            grid_pos.y,
+            grid.width as GridInt,
+            grid.height as GridInt,
+        );
+        grid.raw_get_cell_at(&GridPos::new(x, y)).unwrap()
+    }
+}
+
+/// Periodic, but the cells change sign on each wrap around, in x or y. The
+/// negated cells are kept in a copy of the grid, which `refresh` updates.
+#[derive(Debug)]
+pub struct AntiperiodicBoundary<C> {
+    negate: fn(&C) -> C,
+    negated: Vec<C>,
+}
+impl<C> AntiperiodicBoundary<C> {
+    pub fn new(negate: fn(&C) -> C) -> Self {
+        Self {
+            negate,
+            negated: vec![],
+        }
+    }
+}
+impl<C> BoundaryTrait<C> for AntiperiodicBoundary<C> {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        let (width, height) = (grid.width as GridInt, grid.height as GridInt);
+        let wraps = grid_pos.x.div_euclid(width) + grid_pos.y.div_euclid(height);
+        let idx = grid.to_idx(&GridPos::new(
+            modulo(grid_pos.x, width),
+            modulo(grid_pos.y, height),
+        ));
+        if wraps % 2 == 0 {
+            &grid.cells[idx]
+        } else {
+            &self.negated[idx]
+        }
+    }
+    fn refresh(&mut self, cells: &[C]) {
+        self.negated.clear();
+        self.negated.extend(cells.iter().map(self.negate));
+    }
+}
+
+/// Wrapping around in x flips y. Free at the y edges.
+#[derive(Debug)]
+pub struct MobiusBoundary;
+/// Wrapping around in x flips y. Periodic in y.
+#[derive(Debug)]
+pub struct KleinBottleBoundary;
+
+/// Wrap x, flipping y on every odd number of wraps.
+pub(crate) fn flip_wrap(
+    x: GridInt,
+    y: GridInt,
+    width: GridInt,
+    height: GridInt,
+) -> (GridInt, GridInt) {
+    if x.div_euclid(width) % 2 == 0 {
+        (modulo(x, width), y)
+    } else {
+        (modulo(x, width), height - 1 - y)
+    }
+}
+impl<C> BoundaryTrait<C> for MobiusBoundary {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        let (width, height) = (grid.width as GridInt, grid.height as GridInt);
+        let (x, y) = flip_wrap(grid_pos.x, grid_pos.y.clamp(0, height - 1), width, height);
+        grid.raw_get_cell_at(&GridPos::new(x, y)).unwrap()
+    }
+}
+impl<C> BoundaryTrait<C> for KleinBottleBoundary {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        let (width, height) = (grid.width as GridInt, grid.height as GridInt);
+        let (x, y) = flip_wrap(grid_pos.x, modulo(grid_pos.y, height), width, height);
+        grid.raw_get_cell_at(&GridPos::new(x, y)).unwrap()
+    }
+}
+
+/// How a single edge of a `MixedBoundary` treats positions beyond it.
+#[derive(Debug)]
+pub enum Edge<C> {
+    /// Wrap to the opposite edge, which should also be periodic.
+    Periodic,
+    Free,
+    Reflecting,
+    Constant(C),
+}
+impl<C> Edge<C> {
+    /// The in-grid coordinate for `coord` beyond this edge, or the constant cell.
+    fn map(&self, coord: GridInt, n: GridInt) -> Result<GridInt, &C> {
+        match self {
+            Edge::Periodic => Ok(modulo(coord, n)),
+            Edge::Free => Ok(coord.clamp(0, n - 1)),
+            Edge::Reflecting => Ok(reflect(coord, n)),
+            Edge::Constant(cell) => Err(cell),
+        }
+    }
+}
+
+/// A separate `Edge` for each side. Top is y < 0.
+#[derive(Debug)]
+pub struct MixedBoundary<C> {
+    pub left: Edge<C>,
+    pub right: Edge<C>,
+    pub top: Edge<C>,
+    pub bottom: Edge<C>,
+}
+impl<C> MixedBoundary<C> {
+    fn map_coord<'a>(
+        coord: GridInt,
+        n: GridInt,
+        low: &'a Edge<C>,
+        high: &'a Edge<C>,
+    ) -> Result<GridInt, &'a C> {
+        if coord < 0 {
+            low.map(coord, n)
+        } else if coord >= n {
+            high.map(coord, n)
+        } else {
+            Ok(coord)
+        }
+    }
+    pub(crate) fn map_xy(
+        &self,
+        grid_pos: &GridPos,
+        width: GridInt,
+        height: GridInt,
+    ) -> Result<GridPos, &C> {
+        let x = Self::map_coord(grid_pos.x, width, &self.left, &self.right)?;
+        let y = Self::map_coord(grid_pos.y, height, &self.top, &self.bottom)?;
+        Ok(GridPos::new(x, y))
+    }
+}
+impl<C> BoundaryTrait<C> for MixedBoundary<C> {
+    fn grid_map<'a>(&'a self, grid_pos: &GridPos, grid: &'a Grid<C>) -> &'a C {
+        match self.map_xy(grid_pos, grid.width as GridInt, grid.height as GridInt) {
+            Ok(new_grid_pos) => grid.raw_get_cell_at(&new_grid_pos).unwrap(),
+            Err(cell) => cell,
+        }
+    }
+}
+
+// Variants are named after the boundary types, which enum_dispatch needs
+#[allow(clippy::enum_variant_names)]
 #[enum_dispatch(BoundaryTrait<C>)]
 #[derive(Debug)]
 pub enum Boundary<C> {
     PeriodicBoundary,
     ConstantBoundary(ConstantBoundary<C>),
     FreeBoundary,
+    ReflectingBoundary,
+    TwistedBoundary,
+    AntiperiodicBoundary(AntiperiodicBoundary<C>),
+    MobiusBoundary,
+    KleinBottleBoundary,
+    MixedBoundary(MixedBoundary<C>),
+}
+
+#[test]
+fn test_boundary_identifications() {
+    let mut grid = Grid::new_empty(4, 3, PeriodicBoundary.into());
+    grid.cells = (0..12).collect();
+    let at = |grid: &Grid<i32>, x, y| *grid.get_cell_at(GridPos::new(x, y));
+
+    grid.boundary = ReflectingBoundary.into();
+    assert_eq!(at(&grid, -1, 0), at(&grid, 1, 0));
+    assert_eq!(at(&grid, 4, 2), at(&grid, 2, 2));
+    assert_eq!(at(&grid, 0, 3), at(&grid, 0, 1));
+
+    grid.boundary = TwistedBoundary { shift: 1 }.into();
+    assert_eq!(at(&grid, 4, 0), at(&grid, 0, 1));
+    assert_eq!(at(&grid, -1, 0), at(&grid, 3, 2));
+
+    grid.set_boundary(AntiperiodicBoundary::new(|cell: &i32| -cell).into());
+    assert_eq!(at(&grid, 1, 1), 5);
+    assert_eq!((at(&grid, 4, 0), at(&grid, -1, 0)), (0, -3));
+    assert_eq!((at(&grid, 1, 3), at(&grid, 5, 3)), (-1, 1));
+
+    grid.boundary = MobiusBoundary.into();
+    assert_eq!(at(&grid, 4, 0), at(&grid, 0, 2));
+    assert_eq!(at(&grid, -1, 1), at(&grid, 3, 1));
+    assert_eq!(at(&grid, 0, -1), at(&grid, 0, 0));
+
+    grid.boundary = KleinBottleBoundary.into();
+    assert_eq!(at(&grid, 4, 0), at(&grid, 0, 2));
+    assert_eq!(at(&grid, 8, 0), at(&grid, 0, 0));
+    assert_eq!(at(&grid, 0, -1), at(&grid, 0, 2));
+
+    grid.boundary = MixedBoundary {
+        left: Edge::Periodic,
+        right: Edge::Periodic,
+        top: Edge::Constant(-1),
+        bottom: Edge::Free,
+    }
+    .into();
+    assert_eq!(at(&grid, -1, 1), at(&grid, 3, 1));
+    assert_eq!(at(&grid, 1, -1), -1);
+    assert_eq!(at(&grid, -1, -1), -1);
+    assert_eq!(at(&grid, 1, 5), at(&grid, 1, 2));
 }
@@ -6,7 +6,7 @@ pub struct GridPos {
     pub y: GridInt,
 }
 impl GridPos {
-    pub fn new(x: GridInt, y: GridInt) -> Self {
+    pub const fn new(x: GridInt, y: GridInt) -> Self {
         GridPos { x, y }
     }
 }
@@ -1,22 +1,66 @@
 use crate::cell::Cell;
 use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::lattice::Lattice;
+use crate::grid::neighborhood::Neighborhood;
 use crate::grid::Grid;
 
 pub struct GridView<'a, C: Cell> {
     pub origin: GridPos,
     grid: &'a Grid<C>,
+    params: &'a C::Params,
 }
 impl<'a, C: Cell> GridView<'a, C> {
-    pub fn new(origin: GridPos, grid: &Grid<C>) -> GridView<C> {
-        GridView { origin, grid }
+    pub fn new(origin: GridPos, grid: &'a Grid<C>, params: &'a C::Params) -> GridView<'a, C> {
+        GridView {
+            origin,
+            grid,
+            params,
+        }
     }
-    pub fn get_cell_at_coord(&self, x: GridInt, y: GridInt) -> &C {
+    pub fn params(&self) -> &'a C::Params {
+        self.params
+    }
+    pub fn get_cell_at_coord(&self, x: GridInt, y: GridInt) -> &'a C {
         self.grid
             .get_cell_at(GridPos::new(x + self.origin.x, y + self.origin.y))
     }
-    pub fn get_cell_at(&self, pos: GridPos) -> &C {
+    pub fn get_cell_at(&self, pos: GridPos) -> &'a C {
         self.get_cell_at_coord(pos.x, pos.y)
     }
+    /// The cells at each offset of `neighborhood`.
+    pub fn neighbors<'b>(
+        &'b self,
+        neighborhood: &'b Neighborhood,
+    ) -> impl Iterator<Item = &'a C> + 'b {
+        neighborhood
+            .offsets()
+            .iter()
+            .map(move |&offset| self.get_cell_at(offset))
+    }
+    /// The cells at each offset of `neighborhood`, paired with the offset's weight.
+    pub fn weighted_neighbors<'b>(
+        &'b self,
+        neighborhood: &'b Neighborhood,
+    ) -> impl Iterator<Item = (f32, &'a C)> + 'b {
+        neighborhood
+            .weighted_offsets()
+            .map(move |(offset, weight)| (weight, self.get_cell_at(offset)))
+    }
+    pub fn lattice(&self) -> Lattice {
+        self.grid.lattice
+    }
+    /// Cells sharing an edge with the origin on the grid's lattice.
+    pub fn edge_neighbors(&self) -> impl Iterator<Item = &'a C> + '_ {
+        self.neighbors(self.grid.lattice.edge_neighborhood(self.origin))
+    }
+    /// Cells sharing an edge or a corner with the origin on the grid's lattice.
+    pub fn vertex_neighbors(&self) -> impl Iterator<Item = &'a C> + '_ {
+        self.neighbors(self.grid.lattice.vertex_neighborhood(self.origin))
+    }
+    /// Cells in the laplacian stencil of the grid's lattice, with their weights.
+    pub fn laplace_neighbors(&self) -> impl Iterator<Item = (f32, &'a C)> + '_ {
+        self.weighted_neighbors(self.grid.lattice.laplace(self.origin))
+    }
     pub fn grid_width(&self) -> GridInt {
         self.grid.width as GridInt
     }
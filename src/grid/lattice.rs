@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::neighborhood::Neighborhood;
+
+const fn pos(x: GridInt, y: GridInt) -> GridPos {
+    GridPos::new(x, y)
+}
+
+// Associated consts can't be borrowed for 'static, so keep copies of them here
+static SQUARE_EDGE: Neighborhood<'static> = Neighborhood::VON_NEUMANN;
+static SQUARE_VERTEX: Neighborhood<'static> = Neighborhood::MOORE;
+static SQUARE_LAPLACE: Neighborhood<'static> = Neighborhood::LAPLACE;
+
+// Hexagonal cells use "odd-r" offset coordinates: odd rows are shifted half a
+// cell to the right, so the neighbor offsets depend on the row parity.
+static HEX_EVEN_ROW: Neighborhood<'static> = Neighborhood::from_static(&[
+    pos(1, 0),
+    pos(0, -1),
+    pos(-1, -1),
+    pos(-1, 0),
+    pos(-1, 1),
+    pos(0, 1),
+]);
+static HEX_ODD_ROW: Neighborhood<'static> = Neighborhood::from_static(&[
+    pos(1, 0),
+    pos(1, -1),
+    pos(0, -1),
+    pos(-1, 0),
+    pos(0, 1),
+    pos(1, 1),
+]);
+// Finite volume laplacian for unit spacing between hexagon centers
+static HEX_EVEN_ROW_LAPLACE: Neighborhood<'static> = Neighborhood::from_static_weighted(
+    &[
+        pos(0, 0),
+        pos(1, 0),
+        pos(0, -1),
+        pos(-1, -1),
+        pos(-1, 0),
+        pos(-1, 1),
+        pos(0, 1),
+    ],
+    &[-4.0, 2. / 3., 2. / 3., 2. / 3., 2. / 3., 2. / 3., 2. / 3.],
+);
+static HEX_ODD_ROW_LAPLACE: Neighborhood<'static> = Neighborhood::from_static_weighted(
+    &[
+        pos(0, 0),
+        pos(1, 0),
+        pos(1, -1),
+        pos(0, -1),
+        pos(-1, 0),
+        pos(0, 1),
+        pos(1, 1),
+    ],
+    &[-4.0, 2. / 3., 2. / 3., 2. / 3., 2. / 3., 2. / 3., 2. / 3.],
+);
+
+// Triangular cells alternate between pointing up ((x + y) even) and down.
+// Up triangles share their base with the cell below, down triangles with the
+// cell above.
+static TRI_UP_EDGE: Neighborhood<'static> =
+    Neighborhood::from_static(&[pos(1, 0), pos(-1, 0), pos(0, 1)]);
+static TRI_DOWN_EDGE: Neighborhood<'static> =
+    Neighborhood::from_static(&[pos(1, 0), pos(-1, 0), pos(0, -1)]);
+static TRI_UP_VERTEX: Neighborhood<'static> = Neighborhood::from_static(&[
+    pos(-2, 0),
+    pos(-1, 0),
+    pos(1, 0),
+    pos(2, 0),
+    pos(-1, -1),
+    pos(0, -1),
+    pos(1, -1),
+    pos(-2, 1),
+    pos(-1, 1),
+    pos(0, 1),
+    pos(1, 1),
+    pos(2, 1),
+]);
+static TRI_DOWN_VERTEX: Neighborhood<'static> = Neighborhood::from_static(&[
+    pos(-2, 0),
+    pos(-1, 0),
+    pos(1, 0),
+    pos(2, 0),
+    pos(-1, 1),
+    pos(0, 1),
+    pos(1, 1),
+    pos(-2, -1),
+    pos(-1, -1),
+    pos(0, -1),
+    pos(1, -1),
+    pos(2, -1),
+]);
+// Finite volume laplacian for unit spacing between triangle centers
+static TRI_UP_LAPLACE: Neighborhood<'static> = Neighborhood::from_static_weighted(
+    &[pos(0, 0), pos(1, 0), pos(-1, 0), pos(0, 1)],
+    &[-4.0, 4. / 3., 4. / 3., 4. / 3.],
+);
+static TRI_DOWN_LAPLACE: Neighborhood<'static> = Neighborhood::from_static_weighted(
+    &[pos(0, 0), pos(1, 0), pos(-1, 0), pos(0, -1)],
+    &[-4.0, 4. / 3., 4. / 3., 4. / 3.],
+);
+
+/// The shape of the cells tiling the grid. Cells are always stored in a
+/// `width` x `height` array; the lattice decides which cells are adjacent and
+/// how they are laid out on screen.
+///
+/// Periodic boundaries need an even `height` for hexagonal lattices, and an
+/// even `width` and `height` for triangular ones, so that the row / triangle
+/// parity matches across the wrap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Lattice {
+    #[default]
+    Square,
+    Hexagonal,
+    Triangular,
+}
+impl Lattice {
+    fn is_odd_row(pos: GridPos) -> bool {
+        pos.y.rem_euclid(2) == 1
+    }
+    fn is_up_triangle(pos: GridPos) -> bool {
+        (pos.x + pos.y).rem_euclid(2) == 0
+    }
+    /// Cells sharing an edge with the cell at `pos`.
+    pub fn edge_neighborhood(self, pos: GridPos) -> &'static Neighborhood<'static> {
+        match self {
+            Lattice::Square => &SQUARE_EDGE,
+            Lattice::Hexagonal if Self::is_odd_row(pos) => &HEX_ODD_ROW,
+            Lattice::Hexagonal => &HEX_EVEN_ROW,
+            Lattice::Triangular if Self::is_up_triangle(pos) => &TRI_UP_EDGE,
+            Lattice::Triangular => &TRI_DOWN_EDGE,
+        }
+    }
+    /// Cells sharing an edge or a corner with the cell at `pos`.
+    pub fn vertex_neighborhood(self, pos: GridPos) -> &'static Neighborhood<'static> {
+        match self {
+            Lattice::Square => &SQUARE_VERTEX,
+            Lattice::Hexagonal => self.edge_neighborhood(pos),
+            Lattice::Triangular if Self::is_up_triangle(pos) => &TRI_UP_VERTEX,
+            Lattice::Triangular => &TRI_DOWN_VERTEX,
+        }
+    }
+    /// Weighted stencil approximating the laplacian at `pos`, with unit
+    /// distance between neighboring cell centers.
+    pub fn laplace(self, pos: GridPos) -> &'static Neighborhood<'static> {
+        match self {
+            Lattice::Square => &SQUARE_LAPLACE,
+            Lattice::Hexagonal if Self::is_odd_row(pos) => &HEX_ODD_ROW_LAPLACE,
+            Lattice::Hexagonal => &HEX_EVEN_ROW_LAPLACE,
+            Lattice::Triangular if Self::is_up_triangle(pos) => &TRI_UP_LAPLACE,
+            Lattice::Triangular => &TRI_DOWN_LAPLACE,
+        }
+    }
+    /// Size in pixels of the frame needed to draw a `width` x `height` grid.
+    pub fn frame_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Lattice::Square => (width, height),
+            // each hexagon is a 2x2 block, odd rows shifted by a pixel
+            Lattice::Hexagonal => (2 * width + 1, 2 * height),
+            // each triangle is a 2x1 bar along its base: the bottom half of the
+            // row for up triangles, the top half for down triangles
+            Lattice::Triangular => (width + 1, 2 * height),
+        }
+    }
+    /// Pixels covered by the cell at `pos`, as `(x, y)` frame coordinates.
+    pub fn cell_pixels(self, pos: GridPos) -> impl Iterator<Item = (usize, usize)> {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        let (x0, y0, w, h) = match self {
+            Lattice::Square => (x, y, 1, 1),
+            Lattice::Hexagonal => (2 * x + y % 2, 2 * y, 2, 2),
+            Lattice::Triangular if Self::is_up_triangle(pos) => (x, 2 * y + 1, 2, 1),
+            Lattice::Triangular => (x, 2 * y, 2, 1),
+        };
+        (y0..y0 + h).flat_map(move |py| (x0..x0 + w).map(move |px| (px, py)))
+    }
+    /// The cell drawn at frame pixel `(px, py)`. May be outside of the grid.
+    pub fn pixel_to_cell(self, px: isize, py: isize) -> (isize, isize) {
+        match self {
+            Lattice::Square => (px, py),
+            Lattice::Hexagonal => {
+                let y = py.div_euclid(2);
+                ((px - y.rem_euclid(2)).div_euclid(2), y)
+            }
+            Lattice::Triangular => {
+                let y = py.div_euclid(2);
+                // up triangles live on the bottom half of the row
+                let up = py.rem_euclid(2) == 1;
+                let x = if ((px + y).rem_euclid(2) == 0) == up {
+                    px
+                } else {
+                    px - 1
+                };
+                (x, y)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lattice_pixels_round_trip() {
+    for &lattice in &[Lattice::Square, Lattice::Hexagonal, Lattice::Triangular] {
+        let (width, height) = (6, 4);
+        let (frame_width, frame_height) = lattice.frame_size(width, height);
+        let mut covered = vec![0; frame_width * frame_height];
+        for y in 0..height as GridInt {
+            for x in 0..width as GridInt {
+                for (px, py) in lattice.cell_pixels(pos(x, y)) {
+                    covered[px + py * frame_width] += 1;
+                    assert_eq!(
+                        lattice.pixel_to_cell(px as isize, py as isize),
+                        (x as isize, y as isize)
+                    );
+                }
+            }
+        }
+        assert!(covered.iter().all(|&n| n <= 1));
+    }
+}
+
+#[test]
+fn test_lattice_neighbors_are_symmetric() {
+    let neighborhoods: [fn(Lattice, GridPos) -> &'static Neighborhood<'static>; 2] =
+        [Lattice::edge_neighborhood, Lattice::vertex_neighborhood];
+    for &lattice in &[Lattice::Square, Lattice::Hexagonal, Lattice::Triangular] {
+        for neighborhood in neighborhoods.iter() {
+            for (x, y) in (0..4).flat_map(|y| (0..4).map(move |x| (x, y))) {
+                for offset in neighborhood(lattice, pos(x, y)).offsets() {
+                    let other = pos(x + offset.x, y + offset.y);
+                    let reverse = pos(-offset.x, -offset.y);
+                    assert!(neighborhood(lattice, other).offsets().contains(&reverse));
+                }
+            }
+        }
+    }
+}
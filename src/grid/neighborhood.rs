@@ -0,0 +1,265 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::util::stencil_coords;
+
+const MAX_RADIUS: GridInt = 10;
+
+const fn pos(x: GridInt, y: GridInt) -> GridPos {
+    GridPos::new(x, y)
+}
+
+/// A set of offsets relative to a cell, optionally weighted (e.g. a finite
+/// difference stencil).
+#[derive(Clone, Debug)]
+pub struct Neighborhood<'a> {
+    offsets: Cow<'a, [GridPos]>,
+    weights: Option<Cow<'a, [f32]>>,
+}
+impl Neighborhood<'static> {
+    /// The 8 cells sharing an edge or corner.
+    pub const MOORE: Self = Self::from_static(&[
+        pos(1, 0),
+        pos(1, 1),
+        pos(0, 1),
+        pos(-1, 1),
+        pos(-1, 0),
+        pos(-1, -1),
+        pos(0, -1),
+        pos(1, -1),
+    ]);
+    /// The 4 cells sharing an edge.
+    pub const VON_NEUMANN: Self =
+        Self::from_static(&[pos(1, 0), pos(-1, 0), pos(0, 1), pos(0, -1)]);
+    /// Second order central difference laplacian (5 point stencil).
+    pub const LAPLACE: Self = Self::from_static_weighted(
+        &[pos(0, -1), pos(-1, 0), pos(0, 0), pos(1, 0), pos(0, 1)],
+        &[1.0, 1.0, -4.0, 1.0, 1.0],
+    );
+
+    pub const fn from_static(offsets: &'static [GridPos]) -> Self {
+        Self {
+            offsets: Cow::Borrowed(offsets),
+            weights: None,
+        }
+    }
+    pub const fn from_static_weighted(
+        offsets: &'static [GridPos],
+        weights: &'static [f32],
+    ) -> Self {
+        assert!(offsets.len() == weights.len());
+        Self {
+            offsets: Cow::Borrowed(offsets),
+            weights: Some(Cow::Borrowed(weights)),
+        }
+    }
+    fn filtered_square(radius: GridInt, keep: impl Fn(GridInt, GridInt) -> bool) -> Self {
+        let offsets = (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| pos(dx, dy)))
+            .filter(|p| (p.x, p.y) != (0, 0) && keep(p.x, p.y))
+            .collect();
+        Self::custom(offsets)
+    }
+    /// All cells within Chebyshev distance `radius`, excluding the center.
+    pub fn moore(radius: GridInt) -> Self {
+        Self::filtered_square(radius, |_, _| true)
+    }
+    /// All cells within Manhattan distance `radius`, excluding the center.
+    pub fn von_neumann(radius: GridInt) -> Self {
+        Self::filtered_square(radius, |dx, dy| dx.abs() + dy.abs() <= radius)
+    }
+    /// All cells within Euclidean distance `radius`, excluding the center.
+    pub fn circular(radius: f32) -> Self {
+        Self::filtered_square(radius.floor() as GridInt, |dx, dy| {
+            ((dx * dx + dy * dy) as f32) <= radius * radius
+        })
+    }
+    pub fn custom(offsets: Vec<GridPos>) -> Self {
+        Self {
+            offsets: Cow::Owned(offsets),
+            weights: None,
+        }
+    }
+    pub fn weighted(offsets_and_weights: Vec<(GridPos, f32)>) -> Self {
+        let (offsets, weights) = offsets_and_weights.into_iter().unzip();
+        Self {
+            offsets: Cow::Owned(offsets),
+            weights: Some(Cow::Owned(weights)),
+        }
+    }
+    /// Build a weighted neighborhood from a centered stencil, e.g. `[[f32; 3]; 3]`.
+    /// Width and height must be odd. Zero weights are dropped.
+    pub fn from_stencil<R: AsRef<[f32]>>(rows: &[R]) -> Self {
+        let (width, height) = (rows[0].as_ref().len(), rows.len());
+        assert!(width % 2 == 1 && height % 2 == 1);
+        Self::weighted(
+            rows.iter()
+                .flat_map(|row| row.as_ref().iter().copied())
+                .zip(stencil_coords(width as GridInt, height as GridInt))
+                .filter(|(weight, _)| *weight != 0.0)
+                .map(|(weight, offset)| (offset, weight))
+                .collect(),
+        )
+    }
+}
+impl<'a> Neighborhood<'a> {
+    pub fn offsets(&self) -> &[GridPos] {
+        &self.offsets
+    }
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+    /// Offsets paired with their weights. Unweighted neighborhoods have weight 1.
+    pub fn weighted_offsets(&self) -> impl Iterator<Item = (GridPos, f32)> + '_ {
+        let weights = self.weights.as_deref();
+        self.offsets
+            .iter()
+            .enumerate()
+            .map(move |(i, &offset)| (offset, weights.map_or(1.0, |w| w[i])))
+    }
+}
+
+/// A neighborhood as written in a config section.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborhoodChoice {
+    /// The lattice's own neighbors, `"lattice"`
+    Lattice,
+    /// e.g. `{ moore = 2 }`
+    Moore(GridInt),
+    /// e.g. `{ von_neumann = 2 }`
+    VonNeumann(GridInt),
+    /// e.g. `{ circular = 2.5 }`
+    Circular(f32),
+    /// A centered stencil of weights, which may include the center, e.g.
+    /// `{ stencil = [[1, 0, 1], [0, 0, 0], [1, 0, 1]] }`
+    Stencil(Vec<Vec<f32>>),
+}
+
+/// The `neighborhood` of a config section, built once when the config is read.
+/// Offsets are square grid ones, whatever the lattice.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "NeighborhoodChoice", into = "NeighborhoodChoice")]
+pub struct NeighborhoodParam {
+    choice: NeighborhoodChoice,
+    neighborhood: Option<Neighborhood<'static>>,
+}
+impl NeighborhoodParam {
+    /// The chosen neighborhood, or `None` for the lattice's own.
+    pub fn get(&self) -> Option<&Neighborhood<'static>> {
+        self.neighborhood.as_ref()
+    }
+}
+impl Default for NeighborhoodParam {
+    fn default() -> Self {
+        Self {
+            choice: NeighborhoodChoice::Lattice,
+            neighborhood: None,
+        }
+    }
+}
+impl PartialEq for NeighborhoodParam {
+    fn eq(&self, other: &Self) -> bool {
+        self.choice == other.choice
+    }
+}
+impl TryFrom<NeighborhoodChoice> for NeighborhoodParam {
+    type Error = String;
+    fn try_from(choice: NeighborhoodChoice) -> Result<Self, String> {
+        let check_radius = |radius: f32| {
+            if (1.0..=MAX_RADIUS as f32).contains(&radius) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "the neighborhood radius should be 1 to {}",
+                    MAX_RADIUS
+                ))
+            }
+        };
+        let neighborhood = match &choice {
+            NeighborhoodChoice::Lattice => None,
+            &NeighborhoodChoice::Moore(radius) => {
+                check_radius(radius as f32)?;
+                Some(Neighborhood::moore(radius))
+            }
+            &NeighborhoodChoice::VonNeumann(radius) => {
+                check_radius(radius as f32)?;
+                Some(Neighborhood::von_neumann(radius))
+            }
+            &NeighborhoodChoice::Circular(radius) => {
+                check_radius(radius)?;
+                Some(Neighborhood::circular(radius))
+            }
+            NeighborhoodChoice::Stencil(rows) => {
+                let width = rows.first().map_or(0, Vec::len);
+                let size = 2 * MAX_RADIUS as usize + 1;
+                if width % 2 == 0
+                    || rows.len() % 2 == 0
+                    || rows.iter().any(|row| row.len() != width)
+                {
+                    return Err("the stencil should have an odd number of rows, \
+                        all as long and of odd length"
+                        .to_string());
+                }
+                if width > size || rows.len() > size {
+                    return Err(format!("the stencil should be at most {0}x{0}", size));
+                }
+                Some(Neighborhood::from_stencil(rows))
+            }
+        };
+        Ok(Self {
+            choice,
+            neighborhood,
+        })
+    }
+}
+impl From<NeighborhoodParam> for NeighborhoodChoice {
+    fn from(param: NeighborhoodParam) -> Self {
+        param.choice
+    }
+}
+
+#[test]
+fn test_neighborhood_sizes() {
+    assert_eq!(Neighborhood::moore(1).len(), Neighborhood::MOORE.len());
+    assert_eq!(
+        Neighborhood::von_neumann(1).len(),
+        Neighborhood::VON_NEUMANN.len()
+    );
+    assert_eq!(Neighborhood::moore(2).len(), 24);
+    assert_eq!(Neighborhood::von_neumann(2).len(), 12);
+    assert_eq!(Neighborhood::circular(2.0).len(), 12);
+    assert_eq!(Neighborhood::circular(1.5).len(), 8);
+    let laplace = Neighborhood::from_stencil(&[[0.0, 1.0, 0.0], [1.0, -4.0, 1.0], [0.0, 1.0, 0.0]]);
+    assert_eq!(
+        laplace.weighted_offsets().collect::<Vec<_>>(),
+        Neighborhood::LAPLACE.weighted_offsets().collect::<Vec<_>>()
+    );
+
+    let param = |text: &str| {
+        toml::from_str::<toml::Table>(&format!("neighborhood = {}", text)).unwrap()["neighborhood"]
+            .clone()
+            .try_into::<NeighborhoodParam>()
+            .map(|param| param.get().map(Neighborhood::len))
+    };
+    assert_eq!(param("\"lattice\""), Ok(None));
+    assert_eq!(param("{ moore = 2 }"), Ok(Some(24)));
+    assert_eq!(param("{ circular = 2.0 }"), Ok(Some(12)));
+    assert_eq!(
+        param("{ stencil = [[1, 0, 1], [0, 9, 0], [1, 0, 1]] }"),
+        Ok(Some(5))
+    );
+    for bad in [
+        "\"moore\"",
+        "{ moore = 0 }",
+        "{ von_neumann = 11 }",
+        "{ circular = 0.5 }",
+        "{ stencil = [[1, 0], [0, 1]] }",
+        "{ stencil = [[1, 0, 1], [0], [1, 0, 1]] }",
+    ] {
+        assert!(param(bad).is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::Grid;
+
+/// Sums of a per-cell count over any rectangle of a grid, in constant time. The
+/// table reaches `margin` cells past each edge, through the grid's boundary, so
+/// that the neighborhoods of edge cells are covered too.
+pub struct SummedAreaTable {
+    margin: GridInt,
+    // Width of a row of `sums`, one more than the padded grid
+    stride: usize,
+    // `sums[x + y * stride]` adds up the padded cells above and left of (x, y)
+    sums: Vec<u32>,
+}
+impl SummedAreaTable {
+    pub fn new<C>(grid: &Grid<C>, margin: usize, count: impl Fn(&C) -> u32) -> Self {
+        let margin = margin as GridInt;
+        let width = grid.width as GridInt + 2 * margin;
+        let height = grid.height as GridInt + 2 * margin;
+        let stride = width as usize + 1;
+        let mut sums = vec![0; stride * (height as usize + 1)];
+        for y in 0..height {
+            let mut row_sum = 0;
+            for x in 0..width {
+                row_sum += count(grid.get_cell_at(GridPos::new(x - margin, y - margin)));
+                let idx = (x as usize + 1) + (y as usize + 1) * stride;
+                sums[idx] = sums[idx - stride] + row_sum;
+            }
+        }
+        Self {
+            margin,
+            stride,
+            sums,
+        }
+    }
+
+    /// The total over `x0..=x1` and `y0..=y1`, which may be up to `margin` cells
+    /// outside of the grid.
+    pub fn sum(&self, x0: GridInt, y0: GridInt, x1: GridInt, y1: GridInt) -> u32 {
+        let at = |x: GridInt, y: GridInt| {
+            self.sums[(x + self.margin) as usize + (y + self.margin) as usize * self.stride]
+        };
+        at(x1 + 1, y1 + 1) + at(x0, y0) - at(x0, y1 + 1) - at(x1 + 1, y0)
+    }
+
+    /// The total within Chebyshev distance `radius` of `pos`, center included.
+    pub fn square_sum(&self, pos: GridPos, radius: GridInt) -> u32 {
+        self.sum(
+            pos.x - radius,
+            pos.y - radius,
+            pos.x + radius,
+            pos.y + radius,
+        )
+    }
+
+    /// The total within Manhattan distance `radius` of `pos`, center included. This
+    /// adds up one row at a time, so takes time proportional to `radius`.
+    pub fn diamond_sum(&self, pos: GridPos, radius: GridInt) -> u32 {
+        (-radius..=radius)
+            .map(|dy| {
+                let half_width = radius - dy.abs();
+                let y = pos.y + dy;
+                self.sum(pos.x - half_width, y, pos.x + half_width, y)
+            })
+            .sum()
+    }
+}
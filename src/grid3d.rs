@@ -0,0 +1,163 @@
+pub use grid_view::GridView3;
+
+use crate::cell::{HasColor, Randomize};
+use crate::grid::grid_pos::GridInt;
+use crate::grid3d::boundary::{Boundary3, BoundaryTrait3};
+use crate::grid3d::grid_pos::GridPos3;
+use crate::util::generate_seed;
+
+pub mod boundary;
+pub mod grid_pos;
+pub mod grid_view;
+
+/// How a 3D grid is flattened onto the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// Draw the cells with the given z coordinate.
+    Slice(usize),
+    /// Draw the channel-wise maximum color along z.
+    Max,
+}
+
+/// Three dimensional counterpart of `Grid`. Cells are stored x-fastest, then y, then z.
+#[derive(Debug)]
+pub struct Grid3<C> {
+    pub cells: Vec<C>,
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    boundary: Boundary3<C>,
+    // See `Grid::scratch_cells`
+    pub scratch_cells: Vec<C>,
+}
+impl<C> Grid3<C> {
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.scratch_cells, &mut self.cells);
+    }
+    pub fn set_boundary(&mut self, boundary: Boundary3<C>) {
+        self.boundary = boundary;
+    }
+    pub fn to_idx(&self, grid_pos: &GridPos3) -> usize {
+        grid_pos.x as usize + self.width * (grid_pos.y as usize + self.height * grid_pos.z as usize)
+    }
+    pub fn get_cell_at(&self, grid_pos: GridPos3) -> &C {
+        self.boundary.grid_map3(&grid_pos, self)
+    }
+    pub fn set_scatch_cell_at(&mut self, grid_pos: GridPos3, cell: C) {
+        let idx = self.to_idx(&grid_pos);
+        self.scratch_cells[idx] = cell;
+    }
+    pub fn get_grid_pos_iter(&self) -> impl Iterator<Item = GridPos3> {
+        let (width, height, depth) = (
+            self.width as GridInt,
+            self.height as GridInt,
+            self.depth as GridInt,
+        );
+        (0..depth).flat_map(move |z| {
+            (0..height).flat_map(move |y| (0..width).map(move |x| GridPos3::new(x, y, z)))
+        })
+    }
+    pub fn grid_idx(&self, x: isize, y: isize, z: isize) -> Option<usize> {
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        if x < self.width && y < self.height && z < self.depth {
+            Some(x + self.width * (y + self.height * z))
+        } else {
+            None
+        }
+    }
+    pub fn raw_get_cell_at(&self, grid_pos: &GridPos3) -> Option<&C> {
+        self.grid_idx(
+            grid_pos.x as isize,
+            grid_pos.y as isize,
+            grid_pos.z as isize,
+        )
+        .map(|idx| &self.cells[idx])
+    }
+}
+impl<C: Clone + Default> Grid3<C> {
+    pub fn new_empty(width: usize, height: usize, depth: usize, boundary: Boundary3<C>) -> Self {
+        assert!(width != 0 && height != 0 && depth != 0);
+        let size = width
+            .checked_mul(height)
+            .and_then(|area| area.checked_mul(depth))
+            .expect("too big");
+        Self {
+            cells: vec![C::default(); size],
+            scratch_cells: vec![C::default(); size],
+            width,
+            height,
+            depth,
+            boundary,
+        }
+    }
+}
+impl<C: Default> Grid3<C> {
+    pub fn clear(&mut self) {
+        for c in self.cells.iter_mut() {
+            *c = C::default();
+        }
+    }
+}
+impl<C: Clone + Default + Randomize> Grid3<C> {
+    pub fn randomize(&mut self, params: &C::Params) {
+        let mut rng: randomize::PCG32 = generate_seed().into();
+        for grid_pos in self.get_grid_pos_iter() {
+            let idx = self.to_idx(&grid_pos);
+            self.cells[idx] = C::random(&mut rng, grid_pos.xy(), params);
+        }
+    }
+    pub fn new_random(
+        width: usize,
+        height: usize,
+        depth: usize,
+        boundary: Boundary3<C>,
+        params: &C::Params,
+    ) -> Self {
+        let mut result = Self::new_empty(width, height, depth, boundary);
+        result.randomize(params);
+        result
+    }
+}
+impl<C: HasColor> Grid3<C> {
+    /// Draw a `width` x `height` image of the grid.
+    pub fn draw(&self, screen: &mut [u8], projection: Projection) {
+        let area = self.width * self.height;
+        debug_assert_eq!(screen.len(), 4 * area);
+        match projection {
+            Projection::Slice(z) => {
+                let slice = &self.cells[z * area..(z + 1) * area];
+                for (c, pix) in slice.iter().zip(screen.chunks_exact_mut(4)) {
+                    pix.copy_from_slice(&c.draw());
+                }
+            }
+            Projection::Max => {
+                screen.iter_mut().for_each(|byte| *byte = 0);
+                for slice in self.cells.chunks_exact(area) {
+                    for (c, pix) in slice.iter().zip(screen.chunks_exact_mut(4)) {
+                        for (channel, value) in pix.iter_mut().zip(c.draw().iter()) {
+                            *channel = (*channel).max(*value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_grid3_periodic_wrap() {
+    use crate::grid::boundary::PeriodicBoundary;
+
+    let mut grid = Grid3::<i32>::new_empty(3, 4, 5, PeriodicBoundary.into());
+    for (idx, cell) in grid.cells.iter_mut().enumerate() {
+        *cell = idx as i32;
+    }
+    let pos = GridPos3::new(2, 3, 4);
+    assert_eq!(*grid.get_cell_at(pos), grid.to_idx(&pos) as i32);
+    assert_eq!(
+        grid.get_cell_at(GridPos3::new(-1, -1, -1)),
+        grid.get_cell_at(pos)
+    );
+    assert_eq!(grid.get_cell_at(GridPos3::new(3, 4, 5)), &0);
+    assert_eq!(grid.get_grid_pos_iter().count(), grid.cells.len());
+}
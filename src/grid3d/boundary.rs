@@ -0,0 +1,114 @@
+use crate::grid::boundary::{
+    flip_wrap, reflect, ConstantBoundary, FreeBoundary, KleinBottleBoundary, MixedBoundary,
+    MobiusBoundary, PeriodicBoundary, ReflectingBoundary, TwistedBoundary,
+};
+use crate::grid::grid_pos::GridInt;
+use crate::grid3d::grid_pos::GridPos3;
+use crate::grid3d::Grid3;
+use crate::util::modulo;
+use enum_dispatch::enum_dispatch;
+
+// The 2D boundary types are reused, they just also know how to map 3D positions.
+#[enum_dispatch]
+pub trait BoundaryTrait3<C> {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C;
+}
+
+impl<C> BoundaryTrait3<C> for PeriodicBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let new_grid_pos = GridPos3::new(
+            modulo(grid_pos.x, grid.width as GridInt),
+            modulo(grid_pos.y, grid.height as GridInt),
+            modulo(grid_pos.z, grid.depth as GridInt),
+        );
+        grid.raw_get_cell_at(&new_grid_pos).unwrap()
+    }
+}
+impl<C> BoundaryTrait3<C> for ConstantBoundary<C> {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        match grid.raw_get_cell_at(grid_pos) {
+            Some(cell) => cell,
+            None => &self.0,
+        }
+    }
+}
+impl<C> BoundaryTrait3<C> for FreeBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let new_grid_pos = GridPos3::new(
+            grid_pos.x.clamp(0, (grid.width - 1) as GridInt),
+            grid_pos.y.clamp(0, (grid.height - 1) as GridInt),
+            grid_pos.z.clamp(0, (grid.depth - 1) as GridInt),
+        );
+        grid.raw_get_cell_at(&new_grid_pos).unwrap()
+    }
+}
+
+impl<C> BoundaryTrait3<C> for ReflectingBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let new_grid_pos = GridPos3::new(
+            reflect(grid_pos.x, grid.width as GridInt),
+            reflect(grid_pos.y, grid.height as GridInt),
+            reflect(grid_pos.z, grid.depth as GridInt),
+        );
+        grid.raw_get_cell_at(&new_grid_pos).unwrap()
+    }
+}
+// The x-y identifications below act on every z slice, and z is periodic.
+impl<C> BoundaryTrait3<C> for TwistedBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let (x, y) = self.map_xy(
+            grid_pos.x,
+            grid_pos.y,
+            grid.width as GridInt,
+            grid.height as GridInt,
+        );
+        let z = modulo(grid_pos.z, grid.depth as GridInt);
+        grid.raw_get_cell_at(&GridPos3::new(x, y, z)).unwrap()
+    }
+}
+impl<C> BoundaryTrait3<C> for MobiusBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let (width, height) = (grid.width as GridInt, grid.height as GridInt);
+        let (x, y) = flip_wrap(grid_pos.x, grid_pos.y.clamp(0, height - 1), width, height);
+        let z = modulo(grid_pos.z, grid.depth as GridInt);
+        grid.raw_get_cell_at(&GridPos3::new(x, y, z)).unwrap()
+    }
+}
+impl<C> BoundaryTrait3<C> for KleinBottleBoundary {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        let (width, height) = (grid.width as GridInt, grid.height as GridInt);
+        let (x, y) = flip_wrap(grid_pos.x, modulo(grid_pos.y, height), width, height);
+        let z = modulo(grid_pos.z, grid.depth as GridInt);
+        grid.raw_get_cell_at(&GridPos3::new(x, y, z)).unwrap()
+    }
+}
+impl<C> BoundaryTrait3<C> for MixedBoundary<C> {
+    fn grid_map3<'a>(&'a self, grid_pos: &GridPos3, grid: &'a Grid3<C>) -> &'a C {
+        match self.map_xy(
+            &grid_pos.xy(),
+            grid.width as GridInt,
+            grid.height as GridInt,
+        ) {
+            Ok(xy) => {
+                let z = modulo(grid_pos.z, grid.depth as GridInt);
+                grid.raw_get_cell_at(&GridPos3::new(xy.x, xy.y, z)).unwrap()
+            }
+            Err(cell) => cell,
+        }
+    }
+}
+
+// Variants are named after the boundary types, which enum_dispatch needs
+#[allow(clippy::enum_variant_names)]
+#[enum_dispatch(BoundaryTrait3<C>)]
+#[derive(Debug)]
+pub enum Boundary3<C> {
+    PeriodicBoundary,
+    ConstantBoundary(ConstantBoundary<C>),
+    FreeBoundary,
+    ReflectingBoundary,
+    TwistedBoundary,
+    MobiusBoundary,
+    KleinBottleBoundary,
+    MixedBoundary(MixedBoundary<C>),
+}
@@ -0,0 +1,17 @@
+use crate::grid::grid_pos::{GridInt, GridPos};
+
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, Debug)]
+pub struct GridPos3 {
+    pub x: GridInt,
+    pub y: GridInt,
+    pub z: GridInt,
+}
+impl GridPos3 {
+    pub const fn new(x: GridInt, y: GridInt, z: GridInt) -> Self {
+        GridPos3 { x, y, z }
+    }
+    /// Drop the z coordinate, e.g. to reuse 2D position dependent cell logic.
+    pub fn xy(&self) -> GridPos {
+        GridPos::new(self.x, self.y)
+    }
+}
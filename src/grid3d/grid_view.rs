@@ -0,0 +1,67 @@
+use crate::cell::Cell3;
+use crate::grid::grid_pos::GridInt;
+use crate::grid3d::grid_pos::GridPos3;
+use crate::grid3d::Grid3;
+
+const fn pos(x: GridInt, y: GridInt, z: GridInt) -> GridPos3 {
+    GridPos3::new(x, y, z)
+}
+
+const EDGE_NEIGHBORS: [GridPos3; 6] = [
+    pos(1, 0, 0),
+    pos(-1, 0, 0),
+    pos(0, 1, 0),
+    pos(0, -1, 0),
+    pos(0, 0, 1),
+    pos(0, 0, -1),
+];
+// Second order central difference laplacian (7 point stencil)
+const LAPLACE: [(GridPos3, f32); 7] = [
+    (pos(0, 0, 0), -6.0),
+    (pos(1, 0, 0), 1.0),
+    (pos(-1, 0, 0), 1.0),
+    (pos(0, 1, 0), 1.0),
+    (pos(0, -1, 0), 1.0),
+    (pos(0, 0, 1), 1.0),
+    (pos(0, 0, -1), 1.0),
+];
+
+pub struct GridView3<'a, C: Cell3> {
+    pub origin: GridPos3,
+    grid: &'a Grid3<C>,
+    params: &'a C::Params,
+}
+impl<'a, C: Cell3> GridView3<'a, C> {
+    pub fn new(origin: GridPos3, grid: &'a Grid3<C>, params: &'a C::Params) -> GridView3<'a, C> {
+        GridView3 {
+            origin,
+            grid,
+            params,
+        }
+    }
+    pub fn params(&self) -> &'a C::Params {
+        self.params
+    }
+    pub fn get_cell_at_coord(&self, x: GridInt, y: GridInt, z: GridInt) -> &'a C {
+        self.grid.get_cell_at(GridPos3::new(
+            x + self.origin.x,
+            y + self.origin.y,
+            z + self.origin.z,
+        ))
+    }
+    pub fn get_cell_at(&self, pos: GridPos3) -> &'a C {
+        self.get_cell_at_coord(pos.x, pos.y, pos.z)
+    }
+    /// The 6 cells sharing a face with the origin.
+    pub fn edge_neighbors(&self) -> impl Iterator<Item = &'a C> + '_ {
+        EDGE_NEIGHBORS
+            .iter()
+            .map(move |&dpos| self.get_cell_at(dpos))
+    }
+    /// Cells in the laplacian stencil, with their weights.
+    pub fn laplace_neighbors(&self) -> impl Iterator<Item = (f32, &'a C)> + '_ {
+        LAPLACE
+            .iter()
+            .map(move |&(dpos, weight)| (weight, self.get_cell_at(dpos)))
+    }
+}
@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::cli::HeadlessOptions;
+use crate::config::Config;
+use crate::image::write_png;
+use crate::journal::Replay;
+use crate::npy::NpzRecorder;
+use crate::observables::ObservableLog;
+use crate::recording::Recorder;
+use crate::simulation::Simulation;
+use crate::snapshot::Snapshot;
+
+/// Run a system for a fixed number of steps without opening a window, counting
+/// on from `start_step`.
+///
+/// Frames are rendered with `Simulation::draw` into an in-memory RGBA buffer
+/// and written to `options.out_dir` as binary PPM images. Events from `replay`
+/// are applied before the update following the step they were recorded at.
+pub fn run_headless(
+    mut system: Box<dyn Simulation>,
+    config: &Config,
+    options: HeadlessOptions,
+    start_step: u64,
+    mut replay: Option<Replay>,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&options.out_dir)?;
+    let (frame_width, frame_height) = system.frame_size();
+    let mut frame = vec![0; 4 * frame_width * frame_height];
+    let end_step = start_step + options.steps as u64;
+    let mut recorder = options
+        .record
+        .as_deref()
+        .map(|path| Recorder::new(path, frame_width, frame_height, &config.recording))
+        .transpose()?;
+    let mut npz = options.npz.as_deref().map(NpzRecorder::new);
+    let mut observables = config
+        .observables
+        .as_ref()
+        .map(ObservableLog::new)
+        .transpose()?;
+    if let Some(log) = &mut observables {
+        log.log(start_step, system.as_ref())?;
+    }
+    for step in start_step + 1..=end_step {
+        if let Some(replay) = &mut replay {
+            replay.apply_due(step - 1, system.as_mut());
+        }
+        system.update();
+        if let Some(log) = &mut observables {
+            log.log(step, system.as_ref())?;
+        }
+        if let Some(npz) = &mut npz {
+            if step.is_multiple_of(options.npz_every as u64) {
+                npz.push(step, system.field_data()?)?;
+            }
+        }
+        if let Some(recorder) = &mut recorder {
+            if recorder.wants_frame(step) {
+                system.draw(&mut frame);
+                recorder.write_frame(&frame)?;
+            }
+        }
+        if options.frame_every != 0 && step.is_multiple_of(options.frame_every as u64) {
+            system.draw(&mut frame);
+            let path = options.out_dir.join(format!("frame_{:06}.ppm", step));
+            write_ppm(&path, frame_width, frame_height, &frame)?;
+        }
+    }
+    if let Some(replay) = &mut replay {
+        // Interactions after the last update still show in the final state
+        replay.apply_due(end_step, system.as_mut());
+        if replay.remaining() > 0 {
+            warn!(
+                "{} journal events come after step {} and were not replayed",
+                replay.remaining(),
+                end_step
+            );
+        }
+    }
+    system.draw(&mut frame);
+    let path = options.out_dir.join("final.ppm");
+    write_ppm(&path, frame_width, frame_height, &frame)?;
+    if let Some(path) = options.screenshot {
+        let scale = config.screenshot_scale;
+        write_png(&path, frame_width, frame_height, &frame, scale)?;
+    }
+    if let Some(recorder) = recorder {
+        let path = recorder.path().to_path_buf();
+        let frames = recorder.finish()?;
+        info!("Recorded {} frames to {}", frames, path.display());
+    }
+    if let Some(npz) = npz {
+        let path = npz.path().to_path_buf();
+        let steps = npz.finish()?;
+        info!("Saved fields at {} steps to {}", steps, path.display());
+    }
+    if let Some(path) = options.npy {
+        system.field_data()?.save_npy(&path)?;
+    }
+    if let Some(path) = options.save {
+        Snapshot::take(system.as_ref(), config, end_step)?.save(&path)?;
+    }
+    if let Some(path) = options.export_rle {
+        system.export_pattern(options.region)?.save_rle(&path)?;
+    }
+    info!(
+        "Ran {} steps, output in {:?}",
+        options.steps, options.out_dir
+    );
+    Ok(())
+}
+
+/// Write an RGBA buffer as a binary PPM, dropping the alpha channel.
+pub fn write_ppm(path: &Path, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    for pix in rgba.chunks_exact(4) {
+        writer.write_all(&pix[..3])?;
+    }
+    writer.flush()
+}
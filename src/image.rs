@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use palette::{Hsv, Srgb};
+
+/// One pixel of an image being mapped into cells, with channels from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImagePixel {
+    pub rgb: [f32; 3],
+    /// Brightness above which two state cells are alive
+    pub threshold: f32,
+}
+impl ImagePixel {
+    /// Luma of the (gamma encoded) color, from 0 for black to 1 for white.
+    pub fn brightness(&self) -> f32 {
+        let [r, g, b] = self.rgb;
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+    /// Hue in turns, starting from red. Grays have a hue of 0.
+    pub fn hue(&self) -> f32 {
+        let [r, g, b] = self.rgb;
+        Hsv::from(Srgb::new(r, g, b)).hue.to_positive_degrees() / 360.0
+    }
+    pub fn is_bright(&self) -> bool {
+        self.brightness() > self.threshold
+    }
+}
+
+/// An image decoded to RGB, row by row from the top left.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f32; 3]>,
+}
+impl Image {
+    /// Read a PNG file of any bit depth, in grayscale or color. Alpha is ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("could not read {}: {}", path.display(), e);
+        let mut decoder = png::Decoder::new(File::open(path).map_err(|e| error(&e))?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| error(&e))?;
+        let channels = info.color_type.samples();
+        let channel = |byte: u8| byte as f32 / 255.0;
+        let pixels = data[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pix| match pix.len() {
+                1 | 2 => [channel(pix[0]); 3],
+                _ => [channel(pix[0]), channel(pix[1]), channel(pix[2])],
+            })
+            .collect();
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [f32; 3] {
+        self.pixels[x + y * self.width]
+    }
+}
+
+/// Drop the alpha channel of an RGBA buffer, e.g. from `Simulation::draw`, and
+/// blow each pixel up to a `scale` by `scale` square.
+pub fn upscale_rgb(width: usize, rgba: &[u8], scale: u32) -> Result<Vec<u8>, String> {
+    if scale == 0 {
+        return Err("image scale must be at least 1".to_string());
+    }
+    let scale = scale as usize;
+    let mut data = Vec::with_capacity(3 * rgba.len() / 4 * scale * scale);
+    for row in rgba.chunks_exact(4 * width) {
+        let mut scaled_row = Vec::with_capacity(3 * width * scale);
+        for pix in row.chunks_exact(4) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(&pix[..3]);
+            }
+        }
+        for _ in 0..scale {
+            data.extend_from_slice(&scaled_row);
+        }
+    }
+    Ok(data)
+}
+
+/// Write an RGBA buffer as an RGB PNG, upscaled by `scale`.
+pub fn write_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    scale: u32,
+) -> Result<(), String> {
+    let data = upscale_rgb(width, rgba, scale)?;
+    let scale = scale as usize;
+    let error = |e: &dyn std::fmt::Display| format!("could not write {}: {}", path.display(), e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        (width * scale) as u32,
+        (height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(&data).map_err(|e| error(&e))?;
+    writer.finish().map_err(|e| error(&e))
+}
+
+#[test]
+fn test_png_round_trip() {
+    let path = std::env::temp_dir().join("cellular_test_image.png");
+    let rgba = [0xff, 0, 0, 0, 0, 0xff, 0xff, 0xff];
+    write_png(&path, 2, 1, &rgba, 3).unwrap();
+    let image = Image::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((image.width, image.height), (6, 3));
+    assert_eq!(image.get(2, 2), [1.0, 0.0, 0.0]);
+    assert_eq!(image.get(3, 0), [0.0, 1.0, 1.0]);
+
+    let pixel = |rgb| ImagePixel {
+        rgb,
+        threshold: 0.5,
+    };
+    assert_eq!(pixel(image.get(3, 0)).hue(), 0.5);
+    assert!(pixel(image.get(3, 0)).is_bright());
+    assert!(!pixel(image.get(0, 0)).is_bright());
+}
@@ -7,6 +7,12 @@ pub struct InputResult {
     pub pause: bool,
     pub randomize: bool,
     pub clear: bool,
+    pub save: bool,
+    pub load: bool,
+    pub export: bool,
+    pub screenshot: bool,
+    pub record: bool,
+    pub save_fields: bool,
 }
 
 pub fn handle_input(input: &WinitInputHelper) -> InputResult {
@@ -15,5 +21,11 @@ pub fn handle_input(input: &WinitInputHelper) -> InputResult {
         pause: input.key_pressed(VirtualKeyCode::P),
         randomize: input.key_pressed(VirtualKeyCode::R),
         clear: input.key_pressed(VirtualKeyCode::C),
+        save: input.key_pressed(VirtualKeyCode::F5),
+        load: input.key_pressed(VirtualKeyCode::F9),
+        export: input.key_pressed(VirtualKeyCode::F6),
+        screenshot: input.key_pressed(VirtualKeyCode::F12),
+        record: input.key_pressed(VirtualKeyCode::F7),
+        save_fields: input.key_pressed(VirtualKeyCode::F10),
     }
 }
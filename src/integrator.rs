@@ -0,0 +1,273 @@
+use std::ops::Add;
+
+use num_complex::Complex64;
+use serde::Deserialize;
+
+use crate::cell::Cell;
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::grid_view::GridView;
+use crate::grid::Grid;
+
+type Float = f32;
+
+/// Time stepping scheme used by an `IntegratedSystem`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Integrator {
+    /// Explicit forward Euler.
+    Euler,
+    /// Update velocities first, then positions with the new velocities.
+    /// Same as `Euler` for first order fields.
+    #[default]
+    SemiImplicitEuler,
+    /// Explicit midpoint method.
+    RK2,
+    /// Classic fourth order Runge-Kutta.
+    RK4,
+    /// Velocity Verlet (kick-drift-kick). Second order fields only.
+    Leapfrog,
+}
+
+/// The evolving part of a field cell, e.g. a density or a (position, velocity) pair.
+pub trait FieldState: Copy + Add<Output = Self> + Send + Sync {
+    /// Whether this is a `Phase`, i.e. the state of a field which is second order in time.
+    const SECOND_ORDER: bool = false;
+    fn scale(self, factor: Float) -> Self;
+    /// Advance everything but the position by `derivative * dt`.
+    fn kick(self, derivative: Self, dt: Float) -> Self {
+        self + derivative.scale(dt)
+    }
+    /// Advance the position by `velocity * dt`. Does nothing for first order states.
+    fn drift(self, _dt: Float) -> Self {
+        self
+    }
+}
+impl FieldState for Float {
+    fn scale(self, factor: Float) -> Self {
+        self * factor
+    }
+}
+impl FieldState for Complex64 {
+    fn scale(self, factor: Float) -> Self {
+        self * factor as f64
+    }
+}
+
+/// State of a field obeying `d(position)/dt = velocity`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Phase<T> {
+    pub position: T,
+    pub velocity: T,
+}
+impl<T: Add<Output = T>> Add for Phase<T> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            position: self.position + rhs.position,
+            velocity: self.velocity + rhs.velocity,
+        }
+    }
+}
+impl<T: FieldState> FieldState for Phase<T> {
+    const SECOND_ORDER: bool = true;
+    fn scale(self, factor: Float) -> Self {
+        Self {
+            position: self.position.scale(factor),
+            velocity: self.velocity.scale(factor),
+        }
+    }
+    fn kick(self, derivative: Self, dt: Float) -> Self {
+        Self {
+            position: self.position,
+            velocity: self.velocity + derivative.velocity.scale(dt),
+        }
+    }
+    fn drift(self, dt: Float) -> Self {
+        Self {
+            position: self.position + self.velocity.scale(dt),
+            velocity: self.velocity,
+        }
+    }
+}
+
+/// Cells of a continuous field, whose update is a time integration step.
+pub trait FieldCell: Cell {
+    type State: FieldState;
+    fn dt(params: &Self::Params) -> Float;
+    fn state(&self) -> Self::State;
+    fn with_state(&self, state: Self::State) -> Self;
+    /// Time derivative of the state, given the neighborhood in `grid_view`.
+    fn derivative(&self, grid_view: &GridView<Self>) -> Self::State;
+
+    /// Single semi-implicit Euler step, for use in `Cell::update`.
+    fn semi_implicit_euler(&self, grid_view: &GridView<Self>) -> Self {
+        let dt = Self::dt(grid_view.params());
+        let state = self.state().kick(self.derivative(grid_view), dt);
+        self.with_state(state.drift(dt))
+    }
+}
+
+/// Integrates a grid of field cells with a selectable `Integrator`. Multi-stage
+/// integrators evaluate derivatives on the whole grid at each stage.
+pub struct IntegratedSystem<C: FieldCell> {
+    pub grid: Grid<C>,
+    pub integrator: Integrator,
+    pub params: C::Params,
+}
+impl<C: FieldCell> IntegratedSystem<C> {
+    pub fn new(grid: Grid<C>, integrator: Integrator) -> Result<Self, String> {
+        let mut system = Self {
+            grid,
+            integrator: Integrator::default(),
+            params: C::Params::default(),
+        };
+        system.set_integrator(integrator)?;
+        Ok(system)
+    }
+    pub fn set_integrator(&mut self, integrator: Integrator) -> Result<(), String> {
+        if integrator == Integrator::Leapfrog && !C::State::SECOND_ORDER {
+            return Err("Leapfrog needs a field which is second order in time".to_string());
+        }
+        self.integrator = integrator;
+        Ok(())
+    }
+    pub fn with_params(mut self, params: C::Params) -> Self {
+        self.params = params;
+        self
+    }
+    fn grid_pos(&self, idx: usize) -> GridPos {
+        GridPos::new(
+            (idx % self.grid.width) as GridInt,
+            (idx / self.grid.width) as GridInt,
+        )
+    }
+    fn derivative_at(&self, idx: usize) -> C::State {
+        let grid_pos = self.grid_pos(idx);
+        self.grid.cells[idx].derivative(&GridView::new(grid_pos, &self.grid, &self.params))
+    }
+    fn derivatives(&self) -> Vec<C::State> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            (0..self.grid.cells.len())
+                .into_par_iter()
+                .map(|idx| self.derivative_at(idx))
+                .collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        (0..self.grid.cells.len())
+            .map(|idx| self.derivative_at(idx))
+            .collect()
+    }
+    fn states(&self) -> Vec<C::State> {
+        self.grid.cells.iter().map(|cell| cell.state()).collect()
+    }
+    fn set_states(&mut self, state: impl Fn(usize, C::State) -> C::State) {
+        for (idx, cell) in self.grid.cells.iter_mut().enumerate() {
+            *cell = cell.with_state(state(idx, cell.state()));
+        }
+        self.grid.refresh_boundary();
+    }
+    pub fn toggle(&mut self, x: isize, y: isize) -> bool {
+        match self.grid.grid_idx(x, y) {
+            Some(_i) => {
+                let target_pos = GridPos::new(x as GridInt, y as GridInt);
+                for grid_pos in self.grid.get_grid_pos_iter() {
+                    let idx = self.grid.to_idx(&grid_pos);
+                    self.grid.cells[idx].toggle(&target_pos, &grid_pos)
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn update(&mut self) {
+        // Cells may have been edited since the last update, e.g. toggled
+        self.grid.refresh_boundary();
+        let dt = C::dt(&self.params);
+        match self.integrator {
+            Integrator::Euler => {
+                let k = self.derivatives();
+                self.set_states(|i, s| s + k[i].scale(dt));
+            }
+            Integrator::SemiImplicitEuler => {
+                let k = self.derivatives();
+                self.set_states(|i, s| s.kick(k[i], dt).drift(dt));
+            }
+            Integrator::RK2 => {
+                let y0 = self.states();
+                let k1 = self.derivatives();
+                self.set_states(|i, _| y0[i] + k1[i].scale(dt / 2.0));
+                let k2 = self.derivatives();
+                self.set_states(|i, _| y0[i] + k2[i].scale(dt));
+            }
+            Integrator::RK4 => {
+                let y0 = self.states();
+                let k1 = self.derivatives();
+                self.set_states(|i, _| y0[i] + k1[i].scale(dt / 2.0));
+                let k2 = self.derivatives();
+                self.set_states(|i, _| y0[i] + k2[i].scale(dt / 2.0));
+                let k3 = self.derivatives();
+                self.set_states(|i, _| y0[i] + k3[i].scale(dt));
+                let k4 = self.derivatives();
+                self.set_states(|i, _| {
+                    let k = k1[i] + k2[i].scale(2.0) + k3[i].scale(2.0) + k4[i];
+                    y0[i] + k.scale(dt / 6.0)
+                });
+            }
+            Integrator::Leapfrog => {
+                let k1 = self.derivatives();
+                self.set_states(|i, s| s.kick(k1[i], dt / 2.0).drift(dt));
+                let k2 = self.derivatives();
+                self.set_states(|i, s| s.kick(k2[i], dt / 2.0));
+            }
+        }
+        if C::thermalize(&mut self.grid.cells, &self.params) {
+            self.grid.refresh_boundary();
+        }
+    }
+}
+
+#[test]
+fn test_harmonic_oscillator_orders() {
+    use crate::cell_library::{WaveCell, WaveParams};
+    use crate::grid::boundary::PeriodicBoundary;
+
+    // A uniform massive wave without damping is a harmonic oscillator, x'' = -x
+    let final_error = |integrator| {
+        let params = WaveParams {
+            dt: 0.1,
+            damping: 0.0,
+            mass: 1.0,
+        };
+        let mut grid = Grid::<WaveCell>::new_empty(4, 3, PeriodicBoundary.into());
+        let start = Phase {
+            position: 1.0,
+            velocity: 0.0,
+        };
+        grid.cells = vec![WaveCell::default().with_state(start); 12];
+        let mut system = IntegratedSystem::new(grid, integrator)
+            .unwrap()
+            .with_params(params);
+        let steps = 63;
+        for _ in 0..steps {
+            system.update();
+        }
+        let exact = (steps as Float * 0.1).cos();
+        let states = system.states();
+        let errors = states.iter().map(|s| (s.position - exact).abs());
+        errors.fold(0.0, Float::max)
+    };
+    let errors: Vec<_> = [
+        Integrator::RK4,
+        Integrator::RK2,
+        Integrator::Leapfrog,
+        Integrator::SemiImplicitEuler,
+        Integrator::Euler,
+    ]
+    .iter()
+    .map(|&integrator| final_error(integrator))
+    .collect();
+    assert!(errors[0] < errors[1].min(errors[2]));
+    assert!(errors[1].max(errors[2]) < errors[3]);
+    assert!(errors[3] < errors[4]);
+}
@@ -0,0 +1,202 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use winit::event::VirtualKeyCode;
+
+use crate::config::Config;
+use crate::simulation::Simulation;
+
+/// One interaction with a running system, made after `step` updates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", deny_unknown_fields)]
+pub enum Interaction {
+    Toggle { step: u64, x: isize, y: isize },
+    Randomize { step: u64 },
+    Clear { step: u64 },
+    Key { step: u64, key: VirtualKeyCode },
+}
+impl Interaction {
+    pub fn step(&self) -> u64 {
+        match self {
+            Interaction::Toggle { step, .. }
+            | Interaction::Randomize { step }
+            | Interaction::Clear { step }
+            | Interaction::Key { step, .. } => *step,
+        }
+    }
+
+    /// Make the interaction. Returns `false` for toggles outside of the grid.
+    pub fn apply(&self, system: &mut dyn Simulation) -> bool {
+        match self {
+            Interaction::Toggle { x, y, .. } => return system.toggle(*x, *y),
+            Interaction::Randomize { .. } => system.randomize(),
+            Interaction::Clear { .. } => system.clear(),
+            Interaction::Key { key, .. } => system.key_response(*key),
+        }
+        true
+    }
+}
+
+/// A recorded window session: every interaction, along with the system and the
+/// seed it ran with. Other settings still come from the config when replaying.
+/// e.g.
+/// ```toml
+/// system = "Conway"
+/// seed = 1234
+///
+/// [[events]]
+/// action = "Toggle"
+/// step = 20
+/// x = 5
+/// y = 8
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Journal {
+    pub system: String,
+    #[serde(
+        serialize_with = "serialize_seed",
+        deserialize_with = "deserialize_seed"
+    )]
+    pub seed: u64,
+    #[serde(default)]
+    pub events: Vec<Interaction>,
+}
+impl Journal {
+    pub fn new(system: &str, seed: u64) -> Self {
+        Self {
+            system: system.to_string(),
+            seed,
+            events: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read journal {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("invalid journal {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, text)
+            .map_err(|e| format!("could not write journal {}: {}", path.display(), e))
+    }
+
+    /// Run the journaled system with the journaled seed.
+    pub fn apply_to(&self, config: &mut Config) {
+        config.system = self.system.clone();
+        config.seed = Some(self.seed);
+    }
+}
+
+// TOML integers are i64, so larger seeds are written as strings
+fn serialize_seed<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    match i64::try_from(*seed) {
+        Ok(seed) => serializer.serialize_i64(seed),
+        Err(_) => serializer.serialize_str(&seed.to_string()),
+    }
+}
+
+fn deserialize_seed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seed {
+        Number(u64),
+        Text(String),
+    }
+    match Seed::deserialize(deserializer)? {
+        Seed::Number(seed) => Ok(seed),
+        Seed::Text(text) => text.parse().map_err(de::Error::custom),
+    }
+}
+
+/// Feeds the events of a `Journal` back into a system, in order.
+pub struct Replay {
+    events: Vec<Interaction>,
+    next: usize,
+}
+impl Replay {
+    pub fn new(journal: &Journal) -> Self {
+        let mut events = journal.events.clone();
+        // Stable, so events of the same step keep their order
+        events.sort_by_key(Interaction::step);
+        Self { events, next: 0 }
+    }
+
+    /// Apply every event due by `step`, i.e. before the next update, and return them.
+    pub fn apply_due(&mut self, step: u64, system: &mut dyn Simulation) -> Vec<Interaction> {
+        let due = self.events[self.next..]
+            .iter()
+            .take_while(|event| event.step() <= step)
+            .count();
+        let events = &self.events[self.next..self.next + due];
+        self.next += due;
+        for event in events {
+            event.apply(system);
+        }
+        events.to_vec()
+    }
+
+    /// The number of events which haven't been applied yet.
+    pub fn remaining(&self) -> usize {
+        self.events.len() - self.next
+    }
+}
+
+#[test]
+fn test_journal_replay() {
+    use crate::simulation::build_simulation;
+
+    let text = "grid_width = 8\ngrid_height = 8\nper_frame_updates = 1\n\
+        system = \"Conway\"\nboundary = \"Periodic\"\nseed = 3\n";
+    let mut config: Config = toml::from_str(text).unwrap();
+    let run = |config: &Config, events: &mut dyn FnMut(u64, &mut dyn Simulation)| {
+        crate::util::set_seed(config.seed.unwrap());
+        let mut system = build_simulation(config).unwrap();
+        for step in 0..6 {
+            events(step, system.as_mut());
+            system.update();
+        }
+        system.save_cells().unwrap()
+    };
+    // A session with some interactions...
+    let mut journal = Journal::new("Conway", 3);
+    let recorded = run(&config, &mut |step, system| {
+        let event = match step {
+            1 => Interaction::Clear { step },
+            2 | 3 => Interaction::Toggle { step, x: 3, y: 4 },
+            4 => Interaction::Randomize { step },
+            _ => return,
+        };
+        assert!(event.apply(system));
+        journal.events.push(event);
+    });
+    assert_eq!(journal.events.len(), 4);
+
+    // ...comes back the same from the saved journal, whatever the config's seed
+    let path = std::env::temp_dir().join("cellular_test_journal.toml");
+    journal.save(&path).unwrap();
+    let loaded = Journal::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, journal);
+    config.seed = Some(4);
+    loaded.apply_to(&mut config);
+    let mut replay = Replay::new(&loaded);
+    let replayed = run(&config, &mut |step, system| {
+        replay.apply_due(step, system);
+    });
+    assert_eq!(replay.remaining(), 0);
+    assert_eq!(replayed, recorded);
+
+    // Any seed survives a round trip, although TOML integers stop at `i64::MAX`
+    for seed in [0, i64::MAX as u64, u64::MAX].iter() {
+        let journal = Journal::new("Conway", *seed);
+        journal.save(&path).unwrap();
+        let loaded = Journal::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, journal);
+    }
+}
@@ -0,0 +1,340 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::cell::Cell;
+use crate::grid::grid_pos::{GridInt, GridPos};
+use crate::grid::Grid;
+
+// Longest line written to `.rle` files, as recommended by the format
+const RLE_LINE_LENGTH: usize = 70;
+
+/// A rectangle of cells, e.g. `10,20,30,40` for `x,y,width,height`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: GridInt,
+    pub y: GridInt,
+    pub width: GridInt,
+    pub height: GridInt,
+}
+impl Region {
+    /// The region spanned by two opposite corners, both included.
+    pub fn from_corners(a: (isize, isize), b: (isize, isize)) -> Self {
+        Self {
+            x: a.0.min(b.0) as GridInt,
+            y: a.1.min(b.1) as GridInt,
+            width: ((a.0 - b.0).abs() + 1) as GridInt,
+            height: ((a.1 - b.1).abs() + 1) as GridInt,
+        }
+    }
+    fn contains(&self, pos: GridPos) -> bool {
+        (self.x..self.x + self.width).contains(&pos.x)
+            && (self.y..self.y + self.height).contains(&pos.y)
+    }
+}
+impl FromStr for Region {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<GridInt>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid region {:?}: {}", s, e))?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!(
+                "expected a region as x,y,width,height, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// A two state pattern, as exchanged with other Life programs in `.rle` and
+/// `.cells` (plaintext) files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LifePattern {
+    pub width: usize,
+    pub height: usize,
+    /// From the `rule =` header of `.rle` files
+    pub rule: Option<String>,
+    /// Positions of the live cells, relative to the top left corner
+    pub alive: Vec<(usize, usize)>,
+}
+
+impl LifePattern {
+    /// Read a `.rle` or `.cells` file, for a grid of `grid_size` (width, height).
+    pub fn load(path: &Path, grid_size: (usize, usize)) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read pattern {}: {}", path.display(), e))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("rle") => Self::from_rle(&text, grid_size),
+            Some("cells") => Self::from_plaintext(&text),
+            _ => Err("expected a .rle or .cells file".to_string()),
+        }
+        .map_err(|e| format!("invalid pattern {}: {}", path.display(), e))
+    }
+
+    pub fn save_rle(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_rle())
+            .map_err(|e| format!("could not write pattern {}: {}", path.display(), e))
+    }
+
+    /// Rows of `.` for dead cells and `O` (or `*`) for live ones.
+    pub fn from_rows<S: AsRef<str>>(rows: &[S]) -> Result<Self, String> {
+        let mut pattern = Self::default();
+        for (y, row) in rows.iter().enumerate() {
+            let row = row.as_ref().trim_end();
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '.' => {}
+                    'O' | 'o' | '*' => pattern.alive.push((x, y)),
+                    _ => return Err(format!("unexpected `{}` in pattern row `{}`", c, row)),
+                }
+            }
+            pattern.width = pattern.width.max(row.chars().count());
+            pattern.height = y + 1;
+        }
+        Ok(pattern)
+    }
+
+    /// Parse the plaintext format, i.e. `from_rows` with `!` comment lines.
+    pub fn from_plaintext(text: &str) -> Result<Self, String> {
+        let rows: Vec<_> = text.lines().filter(|line| !line.starts_with('!')).collect();
+        Self::from_rows(&rows)
+    }
+
+    /// Whether the pattern fits in a grid of `grid_size` (width, height).
+    pub fn check_fits(&self, grid_size: (usize, usize)) -> Result<(), String> {
+        check_fits(self.width, self.height, grid_size)
+    }
+
+    /// Parse run length encoded patterns, e.g. `x = 3, y = 3, rule = B3/S23` then `bo$2bo$3o!`.
+    /// Only state 1 (`o`, or `A` in multi-state rules) is alive. Patterns larger than
+    /// `grid_size` are rejected while parsing, as they could take any amount of memory.
+    pub fn from_rle(text: &str, grid_size: (usize, usize)) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = lines.next().ok_or("missing `x = ..., y = ...` header")?;
+        let mut pattern = Self::default();
+        // The rule comes last, and may have commas of its own, e.g. `R5,C0,M1,S34..58,B34..45,NM`
+        let header = match header.split_once("rule") {
+            Some((sizes, rule)) => {
+                let rule = rule
+                    .trim_start()
+                    .strip_prefix('=')
+                    .ok_or("missing `=` after rule")?;
+                pattern.rule = Some(rule.trim().to_string());
+                sizes
+            }
+            None => header,
+        };
+        for item in header.split(',').filter(|item| !item.trim().is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("invalid header item `{}`", item.trim()))?;
+            let (key, value) = (key.trim(), value.trim());
+            let size = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid `{}` in header: `{}`", key, value))
+            };
+            match key {
+                "x" => pattern.width = size()?,
+                "y" => pattern.height = size()?,
+                _ => return Err(format!("unknown header item `{}`", key)),
+            }
+        }
+        pattern.check_fits(grid_size)?;
+
+        let (mut x, mut y) = (0_usize, 0_usize);
+        let mut count = String::new();
+        // Multi-state rules write states past 24 as a letter from `p` to `y`, then `A` to `X`
+        let mut high_state = false;
+        'body: for line in lines {
+            for c in line.chars() {
+                if c.is_ascii_digit() && !high_state {
+                    count.push(c);
+                    continue;
+                }
+                if ('p'..='y').contains(&c) && !high_state {
+                    high_state = true;
+                    continue;
+                }
+                let n = if count.is_empty() {
+                    1
+                } else {
+                    count.parse::<usize>().map_err(|e| e.to_string())?
+                };
+                count.clear();
+                let state = match c {
+                    'b' | '.' if !high_state => 0,
+                    'o' if !high_state => 1,
+                    'A'..='X' if high_state => 25, // or higher
+                    'A'..='X' => c as usize - 'A' as usize + 1,
+                    '$' if !high_state => {
+                        x = 0;
+                        y = y.saturating_add(n);
+                        continue;
+                    }
+                    '!' if !high_state => break 'body,
+                    c if c.is_whitespace() && !high_state => continue,
+                    _ => return Err(format!("unexpected `{}` in pattern", c)),
+                };
+                high_state = false;
+                let end = x.saturating_add(n);
+                if state > 0 {
+                    check_fits(end, y.saturating_add(1), grid_size)?;
+                    pattern.width = pattern.width.max(end);
+                    pattern.height = pattern.height.max(y + 1);
+                }
+                // Dying cells of Generations style rules are left out, as in two state systems
+                if state == 1 {
+                    pattern.alive.extend((x..end).map(|x| (x, y)));
+                }
+                x = end;
+            }
+        }
+        Ok(pattern)
+    }
+
+    pub fn to_rle(&self) -> String {
+        let mut rows = vec![vec![false; self.width]; self.height];
+        for &(x, y) in self.alive.iter() {
+            rows[y][x] = true;
+        }
+        // Runs of (count, tag), with dead cells at the end of rows left out
+        let mut runs: Vec<(usize, char)> = vec![];
+        let mut push = |count: usize, tag: char| match runs.last_mut() {
+            Some((n, last)) if *last == tag => *n += count,
+            _ => runs.push((count, tag)),
+        };
+        for (y, row) in rows.iter().enumerate() {
+            if y > 0 {
+                push(1, '$');
+            }
+            let end = row.iter().rposition(|&alive| alive).map_or(0, |x| x + 1);
+            let mut x = 0;
+            while x < end {
+                let run = row[x..end].iter().take_while(|&&a| a == row[x]).count();
+                push(run, if row[x] { 'o' } else { 'b' });
+                x += run;
+            }
+        }
+        // Trailing empty rows are implied by the size
+        while let Some((_, '$')) = runs.last() {
+            runs.pop();
+        }
+
+        let rule = self.rule.as_deref().unwrap_or("B3/S23");
+        let mut text = format!("x = {}, y = {}, rule = {}\n", self.width, self.height, rule);
+        let mut line = String::new();
+        let tokens = runs.iter().map(|&(n, tag)| match n {
+            1 => tag.to_string(),
+            n => format!("{}{}", n, tag),
+        });
+        for token in tokens.chain(Some("!".to_string())) {
+            if line.len() + token.len() > RLE_LINE_LENGTH {
+                text += &line;
+                text.push('\n');
+                line.clear();
+            }
+            line += &token;
+        }
+        text + &line + "\n"
+    }
+
+    /// The live cells of `grid` within `region`, cropped to their bounding box.
+    pub fn from_grid<C: Cell>(grid: &Grid<C>, region: Option<Region>) -> Result<Self, String> {
+        if grid.cells.first().and_then(Cell::is_alive).is_none() {
+            return Err("only two state systems can export patterns".to_string());
+        }
+        let alive: Vec<GridPos> = grid
+            .get_grid_pos_iter()
+            .filter(|&pos| region.is_none_or(|region| region.contains(pos)))
+            .filter(|&pos| grid.get_cell_at(pos).is_alive() == Some(true))
+            .collect();
+        let min_x = alive.iter().map(|pos| pos.x).min().unwrap_or(0);
+        let min_y = alive.iter().map(|pos| pos.y).min().unwrap_or(0);
+        let max_x = alive.iter().map(|pos| pos.x + 1).max().unwrap_or(0);
+        let max_y = alive.iter().map(|pos| pos.y + 1).max().unwrap_or(0);
+        Ok(Self {
+            width: (max_x - min_x) as usize,
+            height: (max_y - min_y) as usize,
+            rule: None,
+            alive: alive
+                .iter()
+                .map(|pos| ((pos.x - min_x) as usize, (pos.y - min_y) as usize))
+                .collect(),
+        })
+    }
+}
+
+fn check_fits(width: usize, height: usize, grid_size: (usize, usize)) -> Result<(), String> {
+    if width > grid_size.0 || height > grid_size.1 {
+        return Err(format!(
+            "the pattern is at least {}x{}, larger than the {}x{} grid",
+            width, height, grid_size.0, grid_size.1
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_life_pattern_formats() {
+    let grid = (100, 50);
+    let glider = LifePattern::from_rows(&[".O.", "..O", "OOO"]).unwrap();
+    let rle = "#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+    assert_eq!(glider.to_rle(), "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n");
+    let parsed = LifePattern::from_rle(rle, grid).unwrap();
+    assert_eq!(parsed.alive, glider.alive);
+    assert_eq!(parsed.rule.as_deref(), Some("B3/S23"));
+    let plaintext = "!Name: Glider\n.O.\n..O\nOOO\n";
+    assert_eq!(LifePattern::from_plaintext(plaintext).unwrap(), glider);
+
+    // Blank rows in the middle and long lines
+    let gaps = LifePattern::from_rle("x = 80, y = 4\n80o$$\n3bo!", grid).unwrap();
+    assert_eq!((gaps.width, gaps.height, gaps.alive.len()), (80, 4, 81));
+    assert_eq!(gaps.alive.last(), Some(&(3, 2)));
+    assert_eq!(gaps.to_rle(), "x = 80, y = 4, rule = B3/S23\n80o2$3bo!\n");
+    let stripes = LifePattern::from_rows(&["O.".repeat(40)]).unwrap().to_rle();
+    assert!(stripes.lines().all(|line| line.len() <= RLE_LINE_LENGTH));
+    assert_eq!(
+        LifePattern::from_rle(&stripes, grid).unwrap().alive.len(),
+        40
+    );
+    assert!(LifePattern::from_rle("x = 3, y = 3\nb?o!", grid).is_err());
+    let bosco = LifePattern::from_rle("x = 1, y = 1, rule = R5,C0,M1,S34..58,B34..45,NM\no!", grid);
+    assert_eq!(
+        bosco.unwrap().rule.as_deref(),
+        Some("R5,C0,M1,S34..58,B34..45,NM")
+    );
+    // Only state 1 is alive in multi-state patterns, also past state 24
+    let brain = LifePattern::from_rle("x = 5, y = 2, rule = B2/S/C3\nA.B$2.3pA!", grid).unwrap();
+    assert_eq!(brain.alive, [(0, 0)]);
+    assert_eq!((brain.width, brain.height), (5, 2));
+    assert!(LifePattern::from_rle("x = 3, y = 1\npo!", grid).is_err());
+
+    // Patterns larger than the grid are caught before taking up memory
+    let huge = "x = 1000000000000, y = 1\no!";
+    assert!(LifePattern::from_rle(huge, grid).is_err());
+    let huge_body = "x = 0, y = 0\n1000000000000o!";
+    assert!(LifePattern::from_rle(huge_body, grid).is_err());
+    assert!(LifePattern::from_rle("x = 3, y = 3\n3o51$o!", grid).is_err());
+    assert_eq!(
+        "1,2,3,4".parse(),
+        Ok(Region {
+            x: 1,
+            y: 2,
+            width: 3,
+            height: 4
+        })
+    );
+}
@@ -3,81 +3,231 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-use cell::{Cell, HasColor, Randomize};
-use config::{read_config, BoundaryConfig, Config, SystemConfig};
-use grid::boundary::{Boundary, ConstantBoundary, FreeBoundary};
-use grid::grid_view::GridView;
-use log::{debug, error};
+use cli::{parse_args, Command};
+use config::{load_config, Config, ConfigWatcher};
+use log::{debug, error, info, warn};
 use pixels::{Error, Pixels, SurfaceTexture};
-use winit::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::path::{Path, PathBuf};
+use winit::event::{ElementState, Event, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit_input_helper::WinitInputHelper;
 
-use crate::cell::System;
-use crate::cell_library::*;
-use crate::generic_system::GenericSystem;
-use crate::grid::boundary::PeriodicBoundary;
-use crate::grid::Grid;
+use crate::headless::run_headless;
+use crate::image::write_png;
+use crate::journal::{Interaction, Journal, Replay};
+use crate::life_pattern::Region;
+use crate::observables::{ObservableLog, ObservablesConfig};
+use crate::recording::{RecordFormat, Recorder};
+use crate::simulation::{build_simulation, list_systems, Simulation};
+use crate::snapshot::Snapshot;
 
 use crate::window::create_window;
 
 mod cell;
 mod cell_library;
+mod cli;
 mod config;
+mod coupled_system;
 mod generic_system;
 mod grid;
+mod grid3d;
+mod headless;
+mod image;
 mod input;
+mod integrator;
+mod journal;
+mod life_pattern;
+mod npy;
+mod observables;
 //mod phased_particle_system;
+mod recording;
+mod scene;
+mod simulation;
+mod snapshot;
 mod util;
 mod window;
 
-fn main() -> Result<(), Error> {
-    let config = read_config();
-    match config.system {
-        SystemConfig::Conway => run_with_cell::<ConwayCell>(config),
-        SystemConfig::XYModel => run_with_cell::<XYModelCell>(config),
-        SystemConfig::Wave => run_with_cell::<WaveCell>(config),
-        SystemConfig::Sandpile => run_with_cell::<AbelianSandpileCell>(config),
-        SystemConfig::Schrodinger => run_with_cell::<SchrodingerCell>(config),
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let args = parse_args(std::env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        std::process::exit(2)
+    });
+    if let Command::ListSystems = args.command {
+        print!("{}", list_systems());
+        return Ok(());
+    }
+    let mut config = load_config(&args.config_path, &args.overrides).unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        std::process::exit(2)
+    });
+    let snapshot = args
+        .load
+        .as_deref()
+        .map(Snapshot::load)
+        .transpose()
+        .unwrap_or_else(|msg| {
+            eprintln!("{}", msg);
+            std::process::exit(2)
+        });
+    if let Some(snapshot) = &snapshot {
+        snapshot.apply_to(&mut config);
     }
+    let replay = args
+        .replay
+        .as_deref()
+        .map(Journal::load)
+        .transpose()
+        .unwrap_or_else(|msg| {
+            eprintln!("{}", msg);
+            std::process::exit(2)
+        })
+        .map(|journal| {
+            journal.apply_to(&mut config);
+            info!("Replaying {} journal events", journal.events.len());
+            Replay::new(&journal)
+        });
+    let seed = config.seed.unwrap_or_else(util::entropy_seed);
+    info!("Using seed {}", seed);
+    util::set_seed(seed);
+    let (system, step) = match &snapshot {
+        Some(snapshot) => (snapshot.restore(&config)?, snapshot.step),
+        None => (build_simulation(&config)?, 0),
+    };
+    match args.command {
+        Command::Window => {
+            let watcher = ConfigWatcher::new(args.config_path, args.overrides);
+            let snapshot_path = args.load.unwrap_or_else(|| PathBuf::from("snapshot.bin"));
+            let journal = args
+                .journal
+                .map(|path| (path, Journal::new(&config.system, seed)));
+            run_window(
+                system,
+                config,
+                watcher,
+                step,
+                snapshot_path,
+                journal,
+                replay,
+            )?
+        }
+        Command::Headless(options) => run_headless(system, &config, options, step, replay)?,
+        Command::ListSystems => unreachable!(),
+    }
+    Ok(())
 }
 
-fn run_with_cell<T: Clone + Default + HasColor + Randomize + Cell + 'static>(
-    config: Config,
-) -> Result<(), Error>
-where
-    GenericSystem<T>: SystemInputs,
-{
-    env_logger::init();
+// Apply changes from the config file, rebuilding the simulation if needed.
+fn reload_config(
+    system: &mut Box<dyn Simulation>,
+    pixels: &mut Pixels,
+    config: &mut Config,
+    new_config: Config,
+) -> Result<(), String> {
+    let changed = config.changed_fields(&new_config);
+    if changed.is_empty() {
+        return Ok(());
+    }
+    if config.needs_rebuild(&new_config) {
+        if let Some(seed) = new_config.seed {
+            util::set_seed(seed);
+        }
+        *system = build_simulation(&new_config)?;
+        let (frame_width, frame_height) = system.frame_size();
+        pixels.resize_buffer(frame_width as u32, frame_height as u32);
+        info!("Rebuilt simulation for changes to {}", changed.join(", "));
+    } else {
+        system.reconfigure(&new_config)?;
+        info!("Applied changes to {}", changed.join(", "));
+    }
+    *config = new_config;
+    Ok(())
+}
+
+// Replace the running simulation with a saved one, returning its step.
+fn load_snapshot(
+    system: &mut Box<dyn Simulation>,
+    pixels: &mut Pixels,
+    config: &mut Config,
+    path: &Path,
+) -> Result<u64, String> {
+    let snapshot = Snapshot::load(path)?;
+    let mut new_config = config.clone();
+    snapshot.apply_to(&mut new_config);
+    *system = snapshot.restore(&new_config)?;
+    let (frame_width, frame_height) = system.frame_size();
+    pixels.resize_buffer(frame_width as u32, frame_height as u32);
+    *config = new_config;
+    Ok(snapshot.step)
+}
+
+// Record to a file or directory named after the current step.
+fn start_recording(
+    system: &dyn Simulation,
+    config: &Config,
+    step: u64,
+) -> Result<Recorder, String> {
+    let path = match config.recording.format {
+        RecordFormat::Gif => PathBuf::from(format!("recording_{:06}.gif", step)),
+        RecordFormat::Png => PathBuf::from(format!("recording_{:06}", step)),
+    };
+    let (frame_width, frame_height) = system.frame_size();
+    Recorder::new(&path, frame_width, frame_height, &config.recording)
+}
+
+fn finish_recording(recorder: Recorder) {
+    let path = recorder.path().to_path_buf();
+    match recorder.finish() {
+        Ok(frames) => info!("Recorded {} frames to {}", frames, path.display()),
+        Err(msg) => error!("{}", msg),
+    }
+}
+
+fn save_journal(path: &Path, journal: &Journal) {
+    match journal.save(path) {
+        Ok(()) => info!(
+            "Journaled {} events to {}",
+            journal.events.len(),
+            path.display()
+        ),
+        Err(msg) => error!("{}", msg),
+    }
+}
+
+fn run_window(
+    mut system: Box<dyn Simulation>,
+    mut config: Config,
+    mut watcher: ConfigWatcher,
+    mut step: u64,
+    snapshot_path: PathBuf,
+    mut journal: Option<(PathBuf, Journal)>,
+    mut replay: Option<Replay>,
+) -> Result<(), Error> {
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
-    let (window, p_width, p_height, mut _hidpi_factor) = create_window(
-        "Cellular",
-        &event_loop,
-        config.grid_width,
-        config.grid_height,
-    );
+    let (frame_width, frame_height) = system.frame_size();
+    let (frame_width, frame_height) = (frame_width as u32, frame_height as u32);
+    let (window, p_width, p_height, mut _hidpi_factor) =
+        create_window("Cellular", &event_loop, frame_width, frame_height);
 
     let surface_texture = SurfaceTexture::new(p_width, p_height, &window);
-
-    let mut system = GenericSystem::<T>::new(Grid::new_random(
-        config.grid_width as usize,
-        config.grid_height as usize,
-        match config.boundary {
-            BoundaryConfig::Periodic => PeriodicBoundary.into(),
-            BoundaryConfig::Constant => ConstantBoundary::empty().into(),
-            BoundaryConfig::Free => FreeBoundary.into(),
-        },
-    ));
-    let mut pixels = Pixels::new(config.grid_width, config.grid_height, surface_texture)?;
+    let mut pixels = Pixels::new(frame_width, frame_height, surface_texture)?;
     let mut paused = false;
 
     let mut draw_state: Option<bool> = None;
+    // Corner where a right drag started, and the region it last selected
+    let mut select_start: Option<(isize, isize)> = None;
+    let mut selection: Option<Region> = None;
+    let mut recorder: Option<Recorder> = None;
+    // The log is reopened whenever the `[observables]` section changes, and stays
+    // closed after an error until then
+    let mut observables: Option<ObservableLog> = None;
+    let mut observables_config: Option<ObservablesConfig> = None;
 
     event_loop.run(move |event, _, control_flow| {
         // The one and only event that winit_input_helper doesn't have for us...
         if let Event::RedrawRequested(_) = event {
-            system.grid.draw(pixels.get_frame());
+            system.draw(pixels.get_frame());
             if pixels
                 .render()
                 .map_err(|e| error!("pixels.render() failed: {}", e))
@@ -87,9 +237,17 @@ where
                 return;
             }
         }
-        if let Event::WindowEvent { event, .. } = &event {
-            if let WindowEvent::KeyboardInput { input, .. } = event {
-                input.virtual_keycode.map(|k| system.key_response(k));
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { input, .. },
+            ..
+        } = &event
+        {
+            // Releases are ignored, or each key would act twice, e.g. moving a 3D slice by 2
+            if let (ElementState::Pressed, Some(k)) = (input.state, input.virtual_keycode) {
+                system.key_response(k);
+                if let Some((_, journal)) = &mut journal {
+                    journal.events.push(Interaction::Key { step, key: k });
+                }
             }
         }
 
@@ -99,6 +257,9 @@ where
             let input_result = input::handle_input(&input);
             // Close events
             if input_result.request_exit {
+                if let Some((path, journal)) = &journal {
+                    save_journal(path, journal);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
@@ -107,12 +268,107 @@ where
                 paused = !paused;
             }
 
+            if let Some(new_config) = watcher.poll() {
+                if journal.is_some() || replay.is_some() {
+                    warn!("Config changes are not journaled, so replays may differ");
+                }
+                if let Err(msg) = new_config.and_then(|new_config| {
+                    reload_config(&mut system, &mut pixels, &mut config, new_config)
+                }) {
+                    error!("Not reloading config: {}", msg);
+                }
+            }
+
+            if config.observables != observables_config {
+                observables_config = config.observables.clone();
+                observables = None;
+                if let Some(settings) = &observables_config {
+                    match ObservableLog::new(settings) {
+                        Ok(log) => {
+                            info!("Logging observables to {}", settings.file.display());
+                            observables = Some(log);
+                        }
+                        Err(msg) => error!("{}", msg),
+                    }
+                }
+            }
+
             if input_result.randomize {
-                system.grid.randomize();
+                system.randomize();
+                if let Some((_, journal)) = &mut journal {
+                    journal.events.push(Interaction::Randomize { step });
+                }
             }
 
             if input_result.clear {
-                system.grid.clear();
+                system.clear();
+                if let Some((_, journal)) = &mut journal {
+                    journal.events.push(Interaction::Clear { step });
+                }
+            }
+
+            if input_result.save {
+                match Snapshot::take(system.as_ref(), &config, step)
+                    .and_then(|snapshot| snapshot.save(&snapshot_path))
+                {
+                    Ok(()) => info!("Saved step {} to {}", step, snapshot_path.display()),
+                    Err(msg) => error!("{}", msg),
+                }
+            }
+
+            if input_result.load && (journal.is_some() || replay.is_some()) {
+                error!("Not loading a snapshot, journals only follow a session from its start");
+            } else if input_result.load {
+                match load_snapshot(&mut system, &mut pixels, &mut config, &snapshot_path) {
+                    Ok(loaded_step) => {
+                        step = loaded_step;
+                        info!("Loaded step {} from {}", step, snapshot_path.display());
+                    }
+                    Err(msg) => error!("{}", msg),
+                }
+            }
+
+            if input_result.export {
+                let path = Path::new("pattern.rle");
+                match system
+                    .export_pattern(selection)
+                    .and_then(|pattern| pattern.save_rle(path))
+                {
+                    Ok(()) => info!("Exported live cells to {}", path.display()),
+                    Err(msg) => error!("{}", msg),
+                }
+            }
+
+            if input_result.screenshot {
+                let path = PathBuf::from(format!("screenshot_{:06}.png", step));
+                let (frame_width, frame_height) = system.frame_size();
+                let (frame, scale) = (pixels.get_frame(), config.screenshot_scale);
+                system.draw(frame);
+                match write_png(&path, frame_width, frame_height, frame, scale) {
+                    Ok(()) => info!("Saved screenshot to {}", path.display()),
+                    Err(msg) => error!("{}", msg),
+                }
+            }
+
+            if input_result.save_fields {
+                let path = PathBuf::from(format!("fields_{:06}.npy", step));
+                match system.field_data().and_then(|data| data.save_npy(&path)) {
+                    Ok(()) => info!("Saved fields to {}", path.display()),
+                    Err(msg) => error!("{}", msg),
+                }
+            }
+
+            if input_result.record {
+                match recorder.take() {
+                    Some(recorder) => finish_recording(recorder),
+                    None => match start_recording(system.as_ref(), &config, step) {
+                        Ok(started) => {
+                            info!("Recording to {}", started.path().display());
+                            recorder = Some(started);
+                        }
+                        Err(msg) => error!("{}", msg),
+                    },
+                }
             }
             // Handle mouse. This is a bit involved since support some simple
             // line drawing (mostly because it makes nice looking patterns).
@@ -132,8 +388,8 @@ where
                         .unwrap_or_else(|pos| pixels.clamp_pixel_pos(pos));
 
                     (
-                        (mx_i as isize, my_i as isize),
-                        (px_i as isize, py_i as isize),
+                        system.pixel_to_cell(mx_i as isize, my_i as isize),
+                        system.pixel_to_cell(px_i as isize, py_i as isize),
                     )
                 })
                 .unwrap_or_default();
@@ -141,6 +397,10 @@ where
             if input.mouse_pressed(0) {
                 debug!("Mouse click at {:?}", mouse_cell);
                 draw_state = Some(system.toggle(mouse_cell.0, mouse_cell.1));
+                if let (Some(true), Some((_, journal))) = (draw_state, &mut journal) {
+                    let (x, y) = mouse_cell;
+                    journal.events.push(Interaction::Toggle { step, x, y });
+                }
             } else if let Some(draw_alive) = draw_state {
                 let release = input.mouse_released(0);
                 let held = input.mouse_held(0);
@@ -164,6 +424,18 @@ where
                     draw_state = None;
                 }
             }
+            if input.mouse_pressed(1) {
+                select_start = Some(mouse_cell);
+            } else if let (Some(start), true) = (select_start, input.mouse_released(1)) {
+                // A right click without dragging goes back to the whole grid
+                selection =
+                    Some(Region::from_corners(start, mouse_cell)).filter(|_| start != mouse_cell);
+                match selection {
+                    Some(r) => info!("Selected region {},{},{},{}", r.x, r.y, r.width, r.height),
+                    None => info!("Cleared selection"),
+                }
+                select_start = None;
+            }
             // Adjust high DPI factor
             if let Some(factor) = input.scale_factor_changed() {
                 _hidpi_factor = factor;
@@ -174,38 +446,36 @@ where
             }
             if !paused || input.key_pressed(VirtualKeyCode::Space) {
                 for _ in 0..config.per_frame_updates {
+                    if let Some(replay) = &mut replay {
+                        let events = replay.apply_due(step, system.as_mut());
+                        if let Some((_, journal)) = &mut journal {
+                            journal.events.extend(events);
+                        }
+                    }
                     system.update();
+                    step += 1;
+                    if let Some(log) = &mut observables {
+                        if let Err(msg) = log.log(step, system.as_ref()) {
+                            error!("Stopped logging observables: {}", msg);
+                            observables = None;
+                        }
+                    }
+                }
+                if let Some(mut active) = recorder.take() {
+                    if active.is_done(step) {
+                        finish_recording(active);
+                    } else if active.wants_frame(step) {
+                        system.draw(pixels.get_frame());
+                        match active.write_frame(pixels.get_frame()) {
+                            Ok(()) => recorder = Some(active),
+                            Err(msg) => error!("Stopped recording: {}", msg),
+                        }
+                    } else {
+                        recorder = Some(active);
+                    }
                 }
             }
             window.request_redraw();
         }
     });
 }
-
-pub trait SystemInputs {
-    fn key_response(&self, vkc: VirtualKeyCode) {}
-}
-impl SystemInputs for GenericSystem<XYModelCell> {
-    fn key_response(&self, vkc: VirtualKeyCode) {
-        match vkc {
-            VirtualKeyCode::E => {
-                let total_energy: f32 = self
-                    .grid
-                    .get_grid_pos_iter()
-                    .map(|p| {
-                        self.grid
-                            .get_cell_at(p)
-                            .get_energy(&GridView::new(p, &self.grid))
-                    })
-                    .sum();
-                println!("Total energy {:?}", total_energy)
-            }
-            _ => (),
-        }
-    }
-}
-
-impl SystemInputs for GenericSystem<ConwayCell> {}
-impl SystemInputs for GenericSystem<WaveCell> {}
-impl SystemInputs for GenericSystem<AbelianSandpileCell> {}
-impl SystemInputs for GenericSystem<SchrodingerCell> {}
@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::cell::Cell;
+
+const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+/// The numeric fields of every cell, from `Cell::field_value`, in row major
+/// order for `shape`: `[height, width]`, or `[depth, height, width]` in 3D.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldData {
+    pub shape: Vec<usize>,
+    pub fields: Vec<(String, Vec<f32>)>,
+}
+impl FieldData {
+    pub fn from_cells<C: Cell>(cells: &[C], shape: Vec<usize>) -> Result<Self, String> {
+        if C::field_names().is_empty() {
+            return Err("this system has no numeric fields to export".to_string());
+        }
+        let fields = C::field_names()
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                (
+                    name.to_string(),
+                    cells.iter().map(|c| c.field_value(i)).collect(),
+                )
+            })
+            .collect();
+        Ok(Self { shape, fields })
+    }
+
+    /// Write a `.npy` file. Several fields are stored as a structured array, so
+    /// that e.g. `np.load(path)["velocity"]` picks one out.
+    pub fn save_npy(&self, path: &Path) -> Result<(), String> {
+        let error =
+            |e: &dyn std::fmt::Display| format!("could not write {}: {}", path.display(), e);
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| error(&e))?);
+        let descr = match &self.fields[..] {
+            [_] => "'<f4'".to_string(),
+            fields => {
+                let names: Vec<_> = fields
+                    .iter()
+                    .map(|(name, _)| format!("('{}', '<f4')", name))
+                    .collect();
+                format!("[{}]", names.join(", "))
+            }
+        };
+        writer
+            .write_all(&npy_header(&descr, &self.shape))
+            .map_err(|e| error(&e))?;
+        let len = self.fields[0].1.len();
+        for i in 0..len {
+            for (_, values) in self.fields.iter() {
+                writer
+                    .write_all(&values[i].to_le_bytes())
+                    .map_err(|e| error(&e))?;
+            }
+        }
+        writer.flush().map_err(|e| error(&e))
+    }
+}
+
+// The version 1.0 header, padded so that the data starts on a multiple of 64 bytes.
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        shape => {
+            let dims: Vec<_> = shape.iter().map(|n| n.to_string()).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut dict = format!(
+        "{{'descr': {}, 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    let unpadded = MAGIC.len() + 2 + dict.len() + 1;
+    dict.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    dict.push('\n');
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+/// Collects `FieldData` over a run, to be written as one `.npz` file with an
+/// array of shape `[steps, ...]` per field, plus the `step` of each entry.
+pub struct NpzRecorder {
+    path: PathBuf,
+    shape: Vec<usize>,
+    steps: Vec<u64>,
+    fields: Vec<(String, Vec<f32>)>,
+}
+impl NpzRecorder {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            shape: vec![],
+            steps: vec![],
+            fields: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn push(&mut self, step: u64, data: FieldData) -> Result<(), String> {
+        if self.steps.is_empty() {
+            self.shape = data.shape;
+            self.fields = data.fields;
+        } else if data.shape != self.shape || data.fields.len() != self.fields.len() {
+            return Err("the layout of the fields changed during the recording".to_string());
+        } else {
+            for ((_, values), (_, new_values)) in self.fields.iter_mut().zip(data.fields) {
+                values.extend(new_values);
+            }
+        }
+        self.steps.push(step);
+        Ok(())
+    }
+
+    /// Write the `.npz` file, returning the number of steps in it.
+    pub fn finish(self) -> Result<usize, String> {
+        let error =
+            |e: &dyn std::fmt::Display| format!("could not write {}: {}", self.path.display(), e);
+        let file = File::create(&self.path).map_err(|e| error(&e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        let mut shape = vec![self.steps.len()];
+        shape.extend_from_slice(&self.shape);
+        for (name, values) in self.fields.iter() {
+            zip.start_file(format!("{}.npy", name), options)
+                .map_err(|e| error(&e))?;
+            zip.write_all(&npy_header("'<f4'", &shape))
+                .map_err(|e| error(&e))?;
+            for value in values {
+                zip.write_all(&value.to_le_bytes()).map_err(|e| error(&e))?;
+            }
+        }
+        zip.start_file("step.npy", options).map_err(|e| error(&e))?;
+        zip.write_all(&npy_header("'<u8'", &[self.steps.len()]))
+            .map_err(|e| error(&e))?;
+        for step in self.steps.iter() {
+            zip.write_all(&step.to_le_bytes()).map_err(|e| error(&e))?;
+        }
+        zip.finish().map_err(|e| error(&e))?;
+        Ok(self.steps.len())
+    }
+}
+
+#[test]
+fn test_npy_layout() {
+    let header = npy_header("'<f4'", &[3, 2]);
+    assert_eq!(header.len() % 64, 0);
+    assert_eq!(header[8..10], [(header.len() - 10) as u8, 0]);
+    let dict = std::str::from_utf8(&header[10..]).unwrap();
+    assert!(dict.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }"));
+    assert!(dict.ends_with(" \n"));
+    assert!(std::str::from_utf8(&npy_header("'<u8'", &[4])[10..])
+        .unwrap()
+        .contains("(4,)"));
+
+    let path = std::env::temp_dir().join("cellular_test_fields.npz");
+    let mut recorder = NpzRecorder::new(&path);
+    for step in [10, 20] {
+        let data = FieldData {
+            shape: vec![1, 2],
+            fields: vec![("value".to_string(), vec![step as f32, 1.5])],
+        };
+        recorder.push(step, data).unwrap();
+    }
+    assert_eq!(recorder.finish(), Ok(2));
+    let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+    let mut data = vec![];
+    std::io::Read::read_to_end(&mut archive.by_name("value.npy").unwrap(), &mut data).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(data.len(), 128 + 4 * 4);
+    assert_eq!(data[128..132], 10.0f32.to_le_bytes());
+    assert_eq!(data[140..144], 1.5f32.to_le_bytes());
+}
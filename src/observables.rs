@@ -0,0 +1,156 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::simulation::Simulation;
+
+/// The `[observables]` section of the config. Logging is off without it.
+/// e.g.
+/// ```toml
+/// [observables]
+/// file = "observables.csv"
+/// every = 10
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObservablesConfig {
+    pub file: PathBuf,
+    #[serde(default = "one")]
+    pub every: u64,
+}
+
+fn one() -> u64 {
+    1
+}
+
+/// Appends a row of `Simulation::observables` to a CSV file every few steps.
+/// Existing files are only appended to if their header has the same columns.
+pub struct ObservableLog {
+    config: ObservablesConfig,
+    writer: BufWriter<File>,
+    // Written once the first row shows which observables the system has
+    columns: Option<Vec<String>>,
+    // The header of the file when opened, `None` if it was empty
+    existing_header: Option<String>,
+}
+impl ObservableLog {
+    pub fn new(config: &ObservablesConfig) -> Result<Self, String> {
+        let path = &config.file;
+        if config.every == 0 {
+            return Err("observables every must be at least 1".to_string());
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+        let existing_header = BufReader::new(&file)
+            .lines()
+            .next()
+            .transpose()
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Ok(Self {
+            config: config.clone(),
+            writer: BufWriter::new(file),
+            columns: None,
+            existing_header,
+        })
+    }
+
+    /// Write a row if `step` is one of the logged steps.
+    pub fn log(&mut self, step: u64, system: &dyn Simulation) -> Result<(), String> {
+        if !step.is_multiple_of(self.config.every) {
+            return Ok(());
+        }
+        let observables = system.observables();
+        let columns: Vec<String> = observables.iter().map(|(name, _)| name.clone()).collect();
+        match &self.columns {
+            None => {
+                let header: Vec<&str> = Some("step")
+                    .into_iter()
+                    .chain(columns.iter().map(String::as_str))
+                    .collect();
+                let header = header.join(",");
+                match &self.existing_header {
+                    None => self.write(&(header + "\n"))?,
+                    Some(existing) if *existing != header => {
+                        return Err(format!(
+                            "{} has the columns {}, not {}",
+                            self.config.file.display(),
+                            existing,
+                            header
+                        ))
+                    }
+                    Some(_) => {}
+                }
+                self.columns = Some(columns);
+            }
+            Some(previous) if *previous != columns => {
+                return Err(format!(
+                    "the observables changed from {} to {}",
+                    previous.join(","),
+                    columns.join(",")
+                ))
+            }
+            Some(_) => {}
+        }
+        let row: Vec<String> = Some(step.to_string())
+            .into_iter()
+            .chain(observables.iter().map(|(_, value)| value.to_string()))
+            .collect();
+        self.write(&(row.join(",") + "\n"))?;
+        // Keep the file readable while the run goes on
+        self.writer
+            .flush()
+            .map_err(|e| format!("could not write {}: {}", self.config.file.display(), e))
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), String> {
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("could not write {}: {}", self.config.file.display(), e))
+    }
+}
+
+#[test]
+fn test_observable_log() {
+    use crate::config::Config;
+    use crate::simulation::build_simulation;
+
+    let text = "grid_width = 6\ngrid_height = 4\nper_frame_updates = 1\n\
+        system = \"Sandpile\"\nboundary = \"Periodic\"\n\
+        scene = [{ kind = \"Fill\", value = 2.0 }]\n";
+    let config: Config = toml::from_str(text).unwrap();
+    let system = build_simulation(&config).unwrap();
+    let file = std::env::temp_dir().join("cellular_test_observables.csv");
+    let _ = std::fs::remove_file(&file);
+    let settings = ObservablesConfig { file, every: 2 };
+    // A second run, e.g. continuing from a snapshot, adds its rows to the same file
+    for steps in [0..4, 4..6].iter() {
+        let mut log = ObservableLog::new(&settings).unwrap();
+        for step in steps.clone() {
+            log.log(step, system.as_ref()).unwrap();
+        }
+    }
+    let csv = std::fs::read_to_string(&settings.file).unwrap();
+    assert_eq!(csv, "step,sand\n0,48\n2,48\n4,48\n");
+
+    // But not if the columns differ
+    let text = text
+        .replace("Sandpile", "Conway")
+        .replace("value = 2.0", "value = 1.0");
+    let config: Config = toml::from_str(&text).unwrap();
+    let other = build_simulation(&config).unwrap();
+    let mut log = ObservableLog::new(&settings).unwrap();
+    let error = log.log(6, other.as_ref()).err().unwrap();
+    assert!(
+        error.contains("step,sand, not step,population"),
+        "{}",
+        error
+    );
+    assert_eq!(std::fs::read_to_string(&settings.file).unwrap(), csv);
+    std::fs::remove_file(&settings.file).unwrap();
+}
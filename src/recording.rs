@@ -0,0 +1,192 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::image::{upscale_rgb, write_png};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum RecordFormat {
+    // One animated GIF file
+    Gif,
+    // A directory of `frame_000000.png`, `frame_000001.png`, ...
+    Png,
+}
+
+/// The `[recording]` section of the config.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConfig {
+    pub format: RecordFormat,
+    /// Keep one of every `every` rendered frames
+    pub every: u32,
+    /// Playback speed of GIFs
+    pub fps: u32,
+    /// Steps outside of `start..=end` are not recorded
+    pub start: u64,
+    pub end: Option<u64>,
+    /// Integer upscaling, as for screenshots
+    pub scale: u32,
+}
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            format: RecordFormat::Gif,
+            every: 1,
+            fps: 30,
+            start: 0,
+            end: None,
+            scale: 1,
+        }
+    }
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Png,
+}
+
+/// Writes rendered frames to a GIF or a PNG sequence, as set up by a `RecordingConfig`.
+pub struct Recorder {
+    settings: RecordingConfig,
+    path: PathBuf,
+    width: usize,
+    height: usize,
+    output: Output,
+    // Frames offered within the step range, and frames kept
+    seen: u64,
+    written: u64,
+}
+impl Recorder {
+    /// Start a recording of `width` by `height` frames. `path` is the GIF file,
+    /// or the directory of PNG frames, which is created if needed.
+    pub fn new(
+        path: &Path,
+        width: usize,
+        height: usize,
+        settings: &RecordingConfig,
+    ) -> Result<Self, String> {
+        if settings.every == 0 || settings.fps == 0 || settings.scale == 0 {
+            return Err("recording every, fps and scale must be at least 1".to_string());
+        }
+        let error =
+            |e: &dyn std::fmt::Display| format!("could not record to {}: {}", path.display(), e);
+        let output = match settings.format {
+            RecordFormat::Gif => {
+                let scaled = |n: usize| u16::try_from(n * settings.scale as usize).ok();
+                let (gif_width, gif_height) = scaled(width)
+                    .zip(scaled(height))
+                    .ok_or_else(|| error(&"frames are too large for a GIF"))?;
+                let file = File::create(path).map_err(|e| error(&e))?;
+                let mut encoder =
+                    gif::Encoder::new(BufWriter::new(file), gif_width, gif_height, &[])
+                        .map_err(|e| error(&e))?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|e| error(&e))?;
+                Output::Gif(encoder)
+            }
+            RecordFormat::Png => {
+                fs::create_dir_all(path).map_err(|e| error(&e))?;
+                Output::Png
+            }
+        };
+        Ok(Self {
+            settings: settings.clone(),
+            path: path.to_path_buf(),
+            width,
+            height,
+            output,
+            seen: 0,
+            written: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the frame rendered at `step` should be passed to `write_frame`.
+    /// Call once per rendered frame.
+    pub fn wants_frame(&mut self, step: u64) -> bool {
+        if step < self.settings.start || self.is_done(step) {
+            return false;
+        }
+        self.seen += 1;
+        (self.seen - 1).is_multiple_of(self.settings.every as u64)
+    }
+
+    /// Whether `step` is past the end of the range, so the recording can be finished.
+    pub fn is_done(&self, step: u64) -> bool {
+        self.settings.end.is_some_and(|end| step > end)
+    }
+
+    pub fn write_frame(&mut self, rgba: &[u8]) -> Result<(), String> {
+        let scale = self.settings.scale;
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let rgb = upscale_rgb(self.width, rgba, scale)?;
+                let scaled = |n: usize| (n * scale as usize) as u16;
+                let (width, height) = (scaled(self.width), scaled(self.height));
+                let mut frame = gif::Frame::from_rgb_speed(width, height, &rgb, 10);
+                frame.delay = (100.0 / self.settings.fps as f32).round() as u16;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|e| format!("could not record to {}: {}", self.path.display(), e))?;
+            }
+            Output::Png => {
+                let path = self.path.join(format!("frame_{:06}.png", self.written));
+                write_png(&path, self.width, self.height, rgba, scale)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flush the output, returning the number of frames written.
+    pub fn finish(self) -> Result<u64, String> {
+        let path = self.path;
+        if let Output::Gif(encoder) = self.output {
+            encoder
+                .into_inner()
+                .map_err(|e| format!("could not record to {}: {}", path.display(), e))?;
+        }
+        Ok(self.written)
+    }
+}
+
+#[test]
+fn test_recording_frames() {
+    let settings = RecordingConfig {
+        every: 2,
+        start: 3,
+        end: Some(8),
+        scale: 2,
+        ..RecordingConfig::default()
+    };
+    let path = std::env::temp_dir().join("cellular_test_recording.gif");
+    let mut recorder = Recorder::new(&path, 2, 1, &settings).unwrap();
+    let rgba = [0xff, 0, 0, 0, 0, 0, 0xff, 0];
+    let mut steps = vec![];
+    for step in 0..10 {
+        if recorder.wants_frame(step) {
+            recorder.write_frame(&rgba).unwrap();
+            steps.push(step);
+        }
+    }
+    assert_eq!(steps, [3, 5, 7]);
+    assert_eq!(recorder.finish(), Ok(3));
+
+    let mut decoder = gif::DecodeOptions::new()
+        .read_info(File::open(&path).unwrap())
+        .unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (4, 2));
+    let mut frames = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    fs::remove_file(&path).unwrap();
+    assert_eq!(frames, 3);
+}
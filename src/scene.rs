@@ -0,0 +1,327 @@
+use std::path::PathBuf;
+
+use num_complex::Complex32;
+use serde::Deserialize;
+
+use crate::cell::{BoundaryValue, FromPixel, Randomize, Superpose};
+use crate::grid::grid_pos::GridInt;
+use crate::grid::Grid;
+use crate::image::{Image, ImagePixel};
+use crate::life_pattern::LifePattern;
+use crate::util::{generate_seed, RandomGenerator};
+
+type Float = f32;
+
+/// One `[[scene]]` entry of the config. Layers are drawn in order, each on top of the
+/// previous ones, starting from an empty grid.
+/// e.g.
+/// ```toml
+/// [[scene]]
+/// kind = "Vortex"
+/// x = 40.0
+/// y = 50.0
+///
+/// [[scene]]
+/// kind = "Vortex"
+/// x = 60.0
+/// y = 50.0
+/// charge = -1
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind", deny_unknown_fields)]
+pub enum Layer {
+    /// Set every cell to a single value
+    Fill { value: Float },
+    /// Replace a fraction `density` of the cells with random ones
+    Random {
+        #[serde(default = "one")]
+        density: Float,
+    },
+    /// Add a gaussian packet with momentum `(kx, ky)`
+    Gaussian {
+        x: Float,
+        y: Float,
+        width: Float,
+        #[serde(default = "one")]
+        amplitude: Float,
+        #[serde(default)]
+        kx: Float,
+        #[serde(default)]
+        ky: Float,
+    },
+    /// Add a winding of `charge` turns around `(x, y)`. Negative charges are antivortices.
+    Vortex {
+        x: Float,
+        y: Float,
+        #[serde(default = "unit_charge")]
+        charge: i32,
+    },
+    /// Drop `grains` grains of sand (or any amount) on one cell
+    Pile {
+        x: GridInt,
+        y: GridInt,
+        grains: Float,
+    },
+    /// Place a Life pattern with its top left corner at `(x, y)`: one of `PATTERNS`
+    /// by `name`, `rows` of `.` (dead) and `O` (alive), or a `.rle` or `.cells` `file`.
+    Pattern {
+        x: GridInt,
+        y: GridInt,
+        name: Option<String>,
+        rows: Option<Vec<String>>,
+        file: Option<PathBuf>,
+    },
+    /// Map a PNG image onto the cells, one pixel per cell from `(x, y)`, or stretched
+    /// over the whole grid with `fit`. How colors become cells depends on the system,
+    /// e.g. Conway cells are alive where the brightness is above `threshold`.
+    Image {
+        file: PathBuf,
+        #[serde(default)]
+        x: GridInt,
+        #[serde(default)]
+        y: GridInt,
+        #[serde(default)]
+        fit: bool,
+        #[serde(default = "half")]
+        threshold: f32,
+    },
+}
+
+fn one() -> Float {
+    1.0
+}
+fn half() -> f32 {
+    0.5
+}
+fn unit_charge() -> i32 {
+    1
+}
+
+pub const PATTERNS: [(&str, &[&str]); 5] = [
+    ("glider", &[".O.", "..O", "OOO"]),
+    ("blinker", &["OOO"]),
+    ("r_pentomino", &[".OO", "OO.", ".O."]),
+    ("acorn", &[".O.....", "...O...", "OO..OOO"]),
+    ("lwss", &[".O..O", "O....", "O...O", "OOOO."]),
+];
+
+impl Layer {
+    fn kind(&self) -> &'static str {
+        match self {
+            Layer::Fill { .. } => "Fill",
+            Layer::Random { .. } => "Random",
+            Layer::Gaussian { .. } => "Gaussian",
+            Layer::Vortex { .. } => "Vortex",
+            Layer::Pile { .. } => "Pile",
+            Layer::Pattern { .. } => "Pattern",
+            Layer::Image { .. } => "Image",
+        }
+    }
+
+    fn pattern(
+        name: &Option<String>,
+        rows: &Option<Vec<String>>,
+        file: &Option<PathBuf>,
+        grid_size: (usize, usize),
+    ) -> Result<LifePattern, String> {
+        let pattern = match (name, rows, file) {
+            (Some(name), None, None) => PATTERNS
+                .iter()
+                .find(|(pattern, _)| pattern == name)
+                .ok_or_else(|| {
+                    let names: Vec<_> = PATTERNS.iter().map(|(name, _)| *name).collect();
+                    format!("unknown pattern `{}`, expected one of {:?}", name, names)
+                })
+                .and_then(|(_, rows)| LifePattern::from_rows(rows)),
+            (None, Some(rows), None) => LifePattern::from_rows(rows),
+            (None, None, Some(file)) => LifePattern::load(file, grid_size),
+            _ => Err("Pattern layers need exactly one of `name`, `rows` or `file`".to_string()),
+        }?;
+        pattern.check_fits(grid_size)?;
+        Ok(pattern)
+    }
+
+    /// Draw this layer onto `grid`.
+    pub fn apply<C>(
+        &self,
+        grid: &mut Grid<C>,
+        rng: &mut RandomGenerator,
+        params: &C::Params,
+    ) -> Result<(), String>
+    where
+        C: Randomize + BoundaryValue + Superpose + FromPixel,
+    {
+        let unsupported = || format!("{} layers are not supported by this system", self.kind());
+        let superpose_all = |grid: &mut Grid<C>,
+                             amplitude: &dyn Fn(Float, Float) -> Complex32|
+         -> Result<(), String> {
+            for grid_pos in grid.get_grid_pos_iter() {
+                let idx = grid.to_idx(&grid_pos);
+                let z = amplitude(grid_pos.x as Float, grid_pos.y as Float);
+                grid.cells[idx] = grid.cells[idx].superpose(z).ok_or_else(unsupported)?;
+            }
+            Ok(())
+        };
+        match self {
+            Layer::Fill { value } => {
+                let cell = C::from_value(*value).ok_or_else(unsupported)?;
+                for c in grid.cells.iter_mut() {
+                    *c = cell.clone();
+                }
+            }
+            Layer::Random { density } => {
+                for grid_pos in grid.get_grid_pos_iter() {
+                    if randomize::f32_half_open_right(rng.next_u32()) < *density {
+                        let idx = grid.to_idx(&grid_pos);
+                        grid.cells[idx] = C::random(rng, grid_pos, params);
+                    }
+                }
+            }
+            Layer::Gaussian {
+                x,
+                y,
+                width,
+                amplitude,
+                kx,
+                ky,
+            } => superpose_all(grid, &|px, py| {
+                let (dx, dy) = (px - x, py - y);
+                let envelope = amplitude * (-(dx * dx + dy * dy) / (2.0 * width * width)).exp();
+                Complex32::from_polar(envelope, kx * dx + ky * dy)
+            })?,
+            Layer::Vortex { x, y, charge } => superpose_all(grid, &|px, py| {
+                let angle = (py - y).atan2(px - x);
+                Complex32::new(*charge as Float * angle / std::f32::consts::TAU, 0.0)
+            })?,
+            Layer::Pile { x, y, grains } => {
+                if let Some(idx) = grid.grid_idx(*x as isize, *y as isize) {
+                    let amplitude = Complex32::new(*grains, 0.0);
+                    grid.cells[idx] = grid.cells[idx]
+                        .superpose(amplitude)
+                        .ok_or_else(unsupported)?;
+                }
+            }
+            Layer::Pattern {
+                x,
+                y,
+                name,
+                rows,
+                file,
+            } => {
+                let pattern = Self::pattern(name, rows, file, (grid.width, grid.height))?;
+                let dead = C::from_value(0.0).ok_or_else(unsupported)?;
+                let alive = C::from_value(1.0).ok_or_else(unsupported)?;
+                let mut set = |dx: usize, dy: usize, cell: &C| {
+                    let (px, py) = (*x as isize + dx as isize, *y as isize + dy as isize);
+                    if let Some(idx) = grid.grid_idx(px, py) {
+                        grid.cells[idx] = cell.clone();
+                    }
+                };
+                for dy in 0..pattern.height {
+                    for dx in 0..pattern.width {
+                        set(dx, dy, &dead);
+                    }
+                }
+                for &(dx, dy) in pattern.alive.iter() {
+                    set(dx, dy, &alive);
+                }
+            }
+            Layer::Image {
+                file,
+                x,
+                y,
+                fit,
+                threshold,
+            } => {
+                let image = Image::load(file)?;
+                for grid_pos in grid.get_grid_pos_iter() {
+                    let (ix, iy) = if *fit {
+                        (
+                            grid_pos.x as usize * image.width / grid.width,
+                            grid_pos.y as usize * image.height / grid.height,
+                        )
+                    } else if grid_pos.x >= *x && grid_pos.y >= *y {
+                        ((grid_pos.x - x) as usize, (grid_pos.y - y) as usize)
+                    } else {
+                        continue;
+                    };
+                    if ix >= image.width || iy >= image.height {
+                        continue;
+                    }
+                    let pixel = ImagePixel {
+                        rgb: image.get(ix, iy),
+                        threshold: *threshold,
+                    };
+                    let idx = grid.to_idx(&grid_pos);
+                    grid.cells[idx] = C::from_pixel(&pixel, params).ok_or_else(unsupported)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fill `grid` from the layers of a scene. An empty scene gives a random grid.
+pub fn draw_scene<C>(grid: &mut Grid<C>, scene: &[Layer], params: &C::Params) -> Result<(), String>
+where
+    C: Default + Randomize + BoundaryValue + Superpose + FromPixel,
+{
+    if scene.is_empty() {
+        grid.randomize(params);
+        return Ok(());
+    }
+    grid.clear();
+    let mut rng: RandomGenerator = generate_seed().into();
+    for layer in scene {
+        layer.apply(grid, &mut rng, params)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_scene_layers() {
+    use crate::cell_library::{AbelianSandpileCell, ConwayCell, SandpileParams};
+    use crate::grid::boundary::PeriodicBoundary;
+    use crate::grid::grid_pos::GridPos;
+
+    let scene: Vec<Layer> = toml::from_str::<toml::Table>(
+        "[[scene]]\nkind = \"Pattern\"\nx = 1\ny = 2\nname = \"glider\"\n",
+    )
+    .unwrap()["scene"]
+        .clone()
+        .try_into()
+        .unwrap();
+    let mut grid = Grid::<ConwayCell>::new_empty(8, 8, PeriodicBoundary.into());
+    draw_scene(&mut grid, &scene, &Default::default()).unwrap();
+    let alive = |grid: &Grid<ConwayCell>| {
+        grid.get_grid_pos_iter()
+            .filter(|p| grid.get_cell_at(*p).alive)
+            .map(|p| (p.x, p.y))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(alive(&grid), [(2, 2), (3, 3), (1, 4), (2, 4), (3, 4)]);
+
+    let packet = Layer::Gaussian {
+        x: 4.0,
+        y: 4.0,
+        width: 1.0,
+        amplitude: 2.0,
+        kx: 0.0,
+        ky: 0.0,
+    };
+    assert_eq!(
+        draw_scene(&mut grid, &[packet], &Default::default()),
+        Err("Gaussian layers are not supported by this system".to_string())
+    );
+
+    let mut grid = Grid::<AbelianSandpileCell>::new_empty(8, 8, PeriodicBoundary.into());
+    let pile = Layer::Pile {
+        x: 4,
+        y: 4,
+        grains: 3.0,
+    };
+    let params = SandpileParams { critical_height: 4 };
+    draw_scene(&mut grid, &[Layer::Fill { value: 2.0 }, pile], &params).unwrap();
+    assert_eq!(grid.get_cell_at(GridPos::new(4, 4)).height, 5);
+    assert_eq!(grid.get_cell_at(GridPos::new(0, 0)).height, 2);
+}
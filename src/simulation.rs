@@ -0,0 +1,529 @@
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use winit::event::VirtualKeyCode;
+
+use crate::cell::{BoundaryValue, Cell, Cell3, FromPixel, HasColor, Randomize, Superpose, System};
+use crate::cell_library::*;
+use crate::config::{parse_section, Config, ConfigParams};
+use crate::coupled_system::CoupledSystem;
+use crate::generic_system::{GenericSystem, GenericSystem3};
+use crate::grid::lattice::Lattice;
+use crate::grid::Grid;
+use crate::grid3d::{Grid3, Projection};
+use crate::integrator::{FieldCell, IntegratedSystem, Integrator};
+use crate::life_pattern::{LifePattern, Region};
+use crate::npy::FieldData;
+use crate::scene::draw_scene;
+use crate::snapshot::{decode_cells, encode_cells};
+
+/// Type erased interface to a running system, so that the window and the
+/// headless runner don't need to know about cell types or dimensions.
+pub trait Simulation {
+    fn update(&mut self);
+    /// Size in pixels of the buffer passed to `draw`.
+    fn frame_size(&self) -> (usize, usize);
+    fn draw(&self, frame: &mut [u8]);
+    /// The cell drawn at frame pixel `(px, py)`. May be outside of the grid.
+    fn pixel_to_cell(&self, px: isize, py: isize) -> (isize, isize);
+    fn toggle(&mut self, x: isize, y: isize) -> bool;
+    fn randomize(&mut self);
+    fn clear(&mut self);
+    fn key_response(&mut self, vkc: VirtualKeyCode);
+    /// Apply the settings of `config` which don't need a new grid.
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String>;
+    /// Every cell, serialized for a `Snapshot`.
+    fn save_cells(&self) -> Result<Vec<u8>, String>;
+    /// Replace every cell from the output of `save_cells` for the same system and size.
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String>;
+    /// The numeric fields of every cell, e.g. to save as `.npy`.
+    fn field_data(&self) -> Result<FieldData, String>;
+    /// Measurements of the whole system, from `Cell::observables`. Empty in 3D,
+    /// where configuring observables is an error.
+    fn observables(&self) -> Vec<(String, f64)> {
+        vec![]
+    }
+    /// The live cells within `region`, or the whole grid, for two state systems.
+    fn export_pattern(&self, _region: Option<Region>) -> Result<LifePattern, String> {
+        Err("only two state systems can export patterns".to_string())
+    }
+}
+
+impl<C> Simulation for GenericSystem<C>
+where
+    C: ConfigParams + Default + HasColor + Randomize + BoundaryValue,
+{
+    fn update(&mut self) {
+        System::update(self)
+    }
+    fn frame_size(&self) -> (usize, usize) {
+        self.grid.frame_size()
+    }
+    fn draw(&self, frame: &mut [u8]) {
+        self.grid.draw(frame)
+    }
+    fn pixel_to_cell(&self, px: isize, py: isize) -> (isize, isize) {
+        self.grid.lattice.pixel_to_cell(px, py)
+    }
+    fn toggle(&mut self, x: isize, y: isize) -> bool {
+        System::toggle(self, x, y)
+    }
+    fn randomize(&mut self) {
+        self.grid.randomize(&self.params)
+    }
+    fn clear(&mut self) {
+        self.grid.clear()
+    }
+    fn key_response(&mut self, vkc: VirtualKeyCode) {
+        C::key_response(&self.grid, &self.params, vkc)
+    }
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String> {
+        check_own_integrator(config)?;
+        self.grid.set_boundary(config.boundary.build()?);
+        self.params = C::params(config)?;
+        Ok(())
+    }
+    fn save_cells(&self) -> Result<Vec<u8>, String> {
+        encode_cells(&self.grid.cells)
+    }
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String> {
+        decode_cells(data, &mut self.grid.cells)
+    }
+    fn observables(&self) -> Vec<(String, f64)> {
+        let observables = C::observables(&self.grid, &self.params);
+        observables
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+    fn field_data(&self) -> Result<FieldData, String> {
+        FieldData::from_cells(&self.grid.cells, vec![self.grid.height, self.grid.width])
+    }
+    fn export_pattern(&self, region: Option<Region>) -> Result<LifePattern, String> {
+        let mut pattern = LifePattern::from_grid(&self.grid, region)?;
+        pattern.rule = C::rulestring(&self.params);
+        Ok(pattern)
+    }
+}
+
+impl<C> Simulation for IntegratedSystem<C>
+where
+    C: FieldCell + ConfigParams + Default + HasColor + Randomize + BoundaryValue,
+{
+    fn update(&mut self) {
+        IntegratedSystem::update(self)
+    }
+    fn frame_size(&self) -> (usize, usize) {
+        self.grid.frame_size()
+    }
+    fn draw(&self, frame: &mut [u8]) {
+        self.grid.draw(frame)
+    }
+    fn pixel_to_cell(&self, px: isize, py: isize) -> (isize, isize) {
+        self.grid.lattice.pixel_to_cell(px, py)
+    }
+    fn toggle(&mut self, x: isize, y: isize) -> bool {
+        IntegratedSystem::toggle(self, x, y)
+    }
+    fn randomize(&mut self) {
+        self.grid.randomize(&self.params)
+    }
+    fn clear(&mut self) {
+        self.grid.clear()
+    }
+    fn key_response(&mut self, vkc: VirtualKeyCode) {
+        C::key_response(&self.grid, &self.params, vkc)
+    }
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String> {
+        let boundary = config.boundary.build()?;
+        self.set_integrator(config.integrator)?;
+        self.grid.set_boundary(boundary);
+        self.params = C::params(config)?;
+        Ok(())
+    }
+    fn save_cells(&self) -> Result<Vec<u8>, String> {
+        encode_cells(&self.grid.cells)
+    }
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String> {
+        decode_cells(data, &mut self.grid.cells)
+    }
+    fn observables(&self) -> Vec<(String, f64)> {
+        let observables = C::observables(&self.grid, &self.params);
+        observables
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+    fn field_data(&self) -> Result<FieldData, String> {
+        FieldData::from_cells(&self.grid.cells, vec![self.grid.height, self.grid.width])
+    }
+}
+
+impl<C> Simulation for GenericSystem3<C>
+where
+    C: Cell3 + ConfigParams + Default + HasColor + Randomize + BoundaryValue,
+{
+    fn update(&mut self) {
+        GenericSystem3::update(self)
+    }
+    fn frame_size(&self) -> (usize, usize) {
+        (self.grid.width, self.grid.height)
+    }
+    fn draw(&self, frame: &mut [u8]) {
+        self.grid.draw(frame, self.projection)
+    }
+    fn pixel_to_cell(&self, px: isize, py: isize) -> (isize, isize) {
+        (px, py)
+    }
+    fn toggle(&mut self, x: isize, y: isize) -> bool {
+        GenericSystem3::toggle(self, x, y)
+    }
+    fn randomize(&mut self) {
+        self.grid.randomize(&self.params)
+    }
+    fn clear(&mut self) {
+        self.grid.clear()
+    }
+    fn key_response(&mut self, vkc: VirtualKeyCode) {
+        match vkc {
+            VirtualKeyCode::PageUp | VirtualKeyCode::RBracket => self.move_slice(1),
+            VirtualKeyCode::PageDown | VirtualKeyCode::LBracket => self.move_slice(-1),
+            VirtualKeyCode::M => self.toggle_max_projection(),
+            _ => return,
+        }
+        match self.projection {
+            Projection::Slice(z) => info!("Showing slice z = {}", z),
+            Projection::Max => info!("Showing max projection"),
+        }
+    }
+    fn reconfigure(&mut self, config: &Config) -> Result<(), String> {
+        check_own_integrator(config)?;
+        check_no_observables(config)?;
+        self.grid.set_boundary(config.boundary.build3()?);
+        let params = C::params(config)?;
+        C::check_3d(&params)?;
+        self.params = params;
+        Ok(())
+    }
+    fn save_cells(&self) -> Result<Vec<u8>, String> {
+        encode_cells(&self.grid.cells)
+    }
+    fn load_cells(&mut self, data: &[u8]) -> Result<(), String> {
+        decode_cells(data, &mut self.grid.cells)
+    }
+    fn field_data(&self) -> Result<FieldData, String> {
+        FieldData::from_cells(
+            &self.grid.cells,
+            vec![self.grid.depth, self.grid.height, self.grid.width],
+        )
+    }
+}
+
+/// Only an `IntegratedSystem` can change integrator, other systems always step
+/// with their own scheme.
+pub fn check_own_integrator(config: &Config) -> Result<(), String> {
+    if config.integrator == Integrator::default() {
+        return Ok(());
+    }
+    let dimensions = if config.depth.is_some() { "3D " } else { "" };
+    Err(format!(
+        "{}{} always steps with its own scheme, and can't use the {:?} integrator",
+        dimensions, config.system, config.integrator
+    ))
+}
+
+// `Cell::observables` only measures 2D grids
+fn check_no_observables(config: &Config) -> Result<(), String> {
+    match config.observables {
+        Some(_) => Err("observables are 2D only".to_string()),
+        None => Ok(()),
+    }
+}
+
+fn new_2d<C>(config: &Config) -> Result<Box<dyn Simulation>, String>
+where
+    C: ConfigParams
+        + Default
+        + HasColor
+        + Randomize
+        + BoundaryValue
+        + Superpose
+        + FromPixel
+        + 'static,
+{
+    check_own_integrator(config)?;
+    let params = C::params(config)?;
+    let system = GenericSystem::<C>::new(new_grid(config, &params)?).with_params(params);
+    Ok(Box::new(system))
+}
+
+fn new_integrated<C>(config: &Config) -> Result<Box<dyn Simulation>, String>
+where
+    C: FieldCell
+        + ConfigParams
+        + Default
+        + HasColor
+        + Randomize
+        + BoundaryValue
+        + Superpose
+        + FromPixel
+        + 'static,
+{
+    let params = C::params(config)?;
+    let system = IntegratedSystem::<C>::new(new_grid(config, &params)?, config.integrator)?
+        .with_params(params);
+    Ok(Box::new(system))
+}
+
+fn new_3d<C>(config: &Config, depth: u32) -> Result<Box<dyn Simulation>, String>
+where
+    C: Cell3 + ConfigParams + Default + HasColor + Randomize + BoundaryValue + 'static,
+{
+    check_own_integrator(config)?;
+    let params = C::params(config)?;
+    C::check_3d(&params)?;
+    let grid = Grid3::new_random(
+        config.grid_width as usize,
+        config.grid_height as usize,
+        depth as usize,
+        config.boundary.build3()?,
+        &params,
+    );
+    let system = GenericSystem3::<C>::new(grid).with_params(params);
+    Ok(Box::new(system))
+}
+
+fn new_grid<C: Default + Randomize + BoundaryValue + Superpose + FromPixel>(
+    config: &Config,
+    params: &C::Params,
+) -> Result<Grid<C>, String> {
+    let mut grid = Grid::new_empty(
+        config.grid_width as usize,
+        config.grid_height as usize,
+        config.boundary.build()?,
+    )
+    .with_lattice(config.lattice);
+    draw_scene(&mut grid, &config.scene, params)?;
+    Ok(grid)
+}
+
+fn new_predator_prey(config: &Config) -> Result<Box<dyn Simulation>, String> {
+    check_own_integrator(config)?;
+    let (prey, predator) = (PreyCell::params(config)?, PredatorCell::params(config)?);
+    let system = CoupledSystem::new(
+        config.grid_width as usize,
+        config.grid_height as usize,
+        config.lattice,
+    )
+    .with_field(PREY_FIELD, new_grid::<PreyCell>(config, &prey)?, prey)
+    .with_field(
+        PREDATOR_FIELD,
+        new_grid::<PredatorCell>(config, &predator)?,
+        predator,
+    );
+    Ok(Box::new(system))
+}
+
+type Constructor = fn(&Config) -> Result<Box<dyn Simulation>, String>;
+type Constructor3 = fn(&Config, u32) -> Result<Box<dyn Simulation>, String>;
+
+/// A system which can be selected with `system = "<name>"` in the config.
+pub struct SystemEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Config section holding the system's parameters
+    pub params: Option<ParamsSection>,
+    build: Constructor,
+    build_3d: Option<Constructor3>,
+}
+
+/// A `[<name>]` section of the config, with the `Params` of a cell type.
+#[derive(Clone, Copy)]
+pub struct ParamsSection {
+    pub name: &'static str,
+    /// The defaults, as TOML
+    pub defaults: fn() -> String,
+    /// Whether a value is a valid section, before the system is built
+    pub check: fn(&toml::Value) -> Result<(), String>,
+}
+
+const fn section<C: Cell>(name: &'static str) -> Option<ParamsSection>
+where
+    C::Params: Serialize + DeserializeOwned,
+{
+    Some(ParamsSection {
+        name,
+        defaults: default_params::<C::Params>,
+        check: check_params::<C::Params>,
+    })
+}
+
+fn default_params<P: Default + Serialize>() -> String {
+    let mut table = toml::Table::try_from(P::default()).expect("parameters should be a table");
+    // Most parameters are f32, show them without the noise from widening to f64
+    for (_, value) in table.iter_mut() {
+        if let toml::Value::Float(x) = value {
+            *x = (*x as f32).to_string().parse().unwrap_or(*x);
+        }
+    }
+    table.to_string()
+}
+
+fn check_params<P: DeserializeOwned>(section: &toml::Value) -> Result<(), String> {
+    parse_section::<P>(section.clone()).map(|_| ())
+}
+
+/// Every selectable system. New models only need an entry here.
+pub const SYSTEMS: &[SystemEntry] = &[
+    SystemEntry {
+        name: "Conway",
+        description: "Life-like cellular automata, Conway's Game of Life by default",
+        params: section::<ConwayCell>("conway"),
+        build: new_2d::<ConwayCell>,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "Generations",
+        description: "Life-like automata where cells take several steps to die",
+        params: section::<GenerationsCell>("generations"),
+        build: new_2d::<GenerationsCell>,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "LargerThanLife",
+        description: "Totalistic automata with neighborhoods of range up to 10",
+        params: section::<LargerThanLifeCell>("larger_than_life"),
+        build: new_2d::<LargerThanLifeCell>,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "XYModel",
+        description: "Planar spins with nearest neighbor coupling",
+        params: section::<XYModelCell>("xy_model"),
+        build: new_integrated::<XYModelCell>,
+        build_3d: Some(new_3d::<XYModelCell>),
+    },
+    SystemEntry {
+        name: "Wave",
+        description: "Scalar wave equation",
+        params: section::<WaveCell>("wave"),
+        build: new_integrated::<WaveCell>,
+        build_3d: Some(new_3d::<WaveCell>),
+    },
+    SystemEntry {
+        name: "Sandpile",
+        description: "Abelian sandpile",
+        params: section::<AbelianSandpileCell>("sandpile"),
+        build: new_2d::<AbelianSandpileCell>,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "Schrodinger",
+        description: "Free particle Schrodinger equation",
+        params: section::<SchrodingerCell>("schrodinger"),
+        build: new_2d::<SchrodingerCell>,
+        build_3d: Some(new_3d::<SchrodingerCell>),
+    },
+    SystemEntry {
+        name: "PredatorPrey",
+        description: "Coupled prey and predator densities",
+        params: section::<PreyCell>("predator_prey"),
+        build: new_predator_prey,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "Diffusion",
+        description: "Diffusing density",
+        params: section::<DiffusionCell>("diffusion"),
+        build: new_integrated::<DiffusionCell>,
+        build_3d: Some(new_3d::<DiffusionCell>),
+    },
+    SystemEntry {
+        name: "ComplexDiffusion",
+        description: "Complex density with an imaginary diffusion constant",
+        params: section::<ComplexDiffusionCell>("complex_diffusion"),
+        build: new_integrated::<ComplexDiffusionCell>,
+        build_3d: None,
+    },
+    SystemEntry {
+        name: "ParticleDiffusion",
+        description: "Random walk of discrete particles",
+        params: None,
+        build: new_2d::<ParticleDiffusionCell>,
+        build_3d: None,
+    },
+];
+
+/// Look up a system by its config name.
+pub fn find_system(name: &str) -> Result<&'static SystemEntry, String> {
+    SYSTEMS
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = SYSTEMS.iter().map(|entry| entry.name).collect();
+            format!(
+                "unknown system `{}`, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+/// Build the system described by `config`. Grids with a `depth` are 3D.
+pub fn build_simulation(config: &Config) -> Result<Box<dyn Simulation>, String> {
+    let entry = find_system(&config.system)?;
+    let depth = match config.depth {
+        None => return (entry.build)(config),
+        Some(depth) => depth,
+    };
+    if config.lattice != Lattice::Square {
+        return Err(format!("{:?} lattices are 2D only", config.lattice));
+    }
+    if !config.scene.is_empty() {
+        return Err("scenes are 2D only".to_string());
+    }
+    check_no_observables(config)?;
+    let build_3d = entry
+        .build_3d
+        .ok_or_else(|| format!("{} has no 3D version", entry.name))?;
+    build_3d(config, depth)
+}
+
+/// Text for `--list-systems`: each system with its parameters and their defaults.
+pub fn list_systems() -> String {
+    let mut text = String::new();
+    for entry in SYSTEMS.iter() {
+        let dims = if entry.build_3d.is_some() {
+            "2D, 3D"
+        } else {
+            "2D"
+        };
+        text += &format!("{:<20}{} ({})\n", entry.name, entry.description, dims);
+        if let Some(section) = entry.params {
+            text += &format!("    [{}]\n", section.name);
+            for line in (section.defaults)().lines() {
+                text += &format!("    {}\n", line);
+            }
+        }
+    }
+    text
+}
+
+#[test]
+fn test_every_system_builds() {
+    for entry in SYSTEMS.iter() {
+        let text = format!(
+            "grid_width = 8\ngrid_height = 6\nper_frame_updates = 1\n\
+            system = \"{}\"\nboundary = \"Periodic\"\nseed = 1\n",
+            entry.name
+        );
+        let config: Config = toml::from_str(&text).unwrap();
+        let mut system = build_simulation(&config).unwrap();
+        system.update();
+        assert_eq!(system.frame_size(), (8, 6), "{}", entry.name);
+        if let Some(build_3d) = entry.build_3d {
+            build_3d(&config, 3).unwrap().update();
+        }
+    }
+    assert!(find_system("Life").is_err());
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BoundaryConfig, Config};
+use crate::grid::lattice::Lattice;
+use crate::simulation::{build_simulation, Simulation};
+use crate::util::{seed_source, set_seed_source, RandomGenerator};
+
+const MAGIC: &[u8; 8] = b"CELLSNAP";
+const VERSION: u32 = 1;
+
+/// Everything needed to resume a run: the layout of the grid, every cell, and
+/// the generator behind `generate_seed`. Parameters still come from the config.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub system: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: Option<u32>,
+    pub lattice: Lattice,
+    pub boundary: BoundaryConfig,
+    pub step: u64,
+    pub rng: Option<RandomGenerator>,
+    // From `Simulation::save_cells`
+    cells: Vec<u8>,
+}
+impl Snapshot {
+    pub fn take(system: &dyn Simulation, config: &Config, step: u64) -> Result<Self, String> {
+        Ok(Self {
+            system: config.system.clone(),
+            width: config.grid_width,
+            height: config.grid_height,
+            depth: config.depth,
+            lattice: config.lattice,
+            boundary: config.boundary.clone(),
+            step,
+            rng: seed_source(),
+            cells: system.save_cells()?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let error = |e: &dyn std::fmt::Display| format!("could not save {}: {}", path.display(), e);
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| error(&e))?);
+        writer.write_all(MAGIC).map_err(|e| error(&e))?;
+        writer
+            .write_all(&VERSION.to_le_bytes())
+            .map_err(|e| error(&e))?;
+        bincode::serialize_into(&mut writer, self).map_err(|e| error(&e))?;
+        writer.flush().map_err(|e| error(&e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("could not load {}: {}", path.display(), e);
+        let mut reader = BufReader::new(File::open(path).map_err(|e| error(&e))?);
+        let mut header = [0; 12];
+        reader.read_exact(&mut header).map_err(|e| error(&e))?;
+        if &header[..8] != MAGIC {
+            return Err(error(&"not a snapshot"));
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if version != VERSION {
+            return Err(error(&format!("unsupported snapshot version {}", version)));
+        }
+        bincode::deserialize_from(reader).map_err(|e| error(&e))
+    }
+
+    /// Replace the layout settings of `config` with those of the snapshot.
+    pub fn apply_to(&self, config: &mut Config) {
+        config.system = self.system.clone();
+        config.grid_width = self.width;
+        config.grid_height = self.height;
+        config.depth = self.depth;
+        config.lattice = self.lattice;
+        config.boundary = self.boundary.clone();
+    }
+
+    /// Build the saved system, with parameters from `config`. Call `apply_to` on it first.
+    pub fn restore(&self, config: &Config) -> Result<Box<dyn Simulation>, String> {
+        let mut system = build_simulation(config)?;
+        system.load_cells(&self.cells)?;
+        set_seed_source(self.rng.clone());
+        Ok(system)
+    }
+}
+
+pub fn encode_cells<C: Serialize>(cells: &[C]) -> Result<Vec<u8>, String> {
+    bincode::serialize(cells).map_err(|e| e.to_string())
+}
+
+/// Overwrite `cells` from `encode_cells` output, which must hold as many cells.
+pub fn decode_cells<C: DeserializeOwned>(data: &[u8], cells: &mut Vec<C>) -> Result<(), String> {
+    let decoded: Vec<C> = bincode::deserialize(data).map_err(|e| e.to_string())?;
+    if decoded.len() != cells.len() {
+        return Err(format!(
+            "snapshot has {} cells, the grid has {}",
+            decoded.len(),
+            cells.len()
+        ));
+    }
+    *cells = decoded;
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let text = "grid_width = 12\ngrid_height = 8\nper_frame_updates = 1\n\
+        system = \"Sandpile\"\nboundary = { Twisted = { shift = 3 } }\nseed = 2\n";
+    let config: Config = toml::from_str(text).unwrap();
+    let mut system = build_simulation(&config).unwrap();
+    system.update();
+    let path = std::env::temp_dir().join("cellular_test_snapshot.bin");
+    Snapshot::take(system.as_ref(), &config, 1)
+        .unwrap()
+        .save(&path)
+        .unwrap();
+
+    let snapshot = Snapshot::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut other: Config = toml::from_str(&text.replace("12", "5")).unwrap();
+    snapshot.apply_to(&mut other);
+    assert!(other.changed_fields(&config).is_empty());
+    let restored = snapshot.restore(&other).unwrap();
+    assert_eq!(snapshot.step, 1);
+    assert_eq!(restored.save_cells(), system.save_cells());
+
+    let wrong_size: Config = toml::from_str(&text.replace("12", "5")).unwrap();
+    let error = build_simulation(&wrong_size)
+        .unwrap()
+        .load_cells(&system.save_cells().unwrap());
+    assert_eq!(
+        error,
+        Err("snapshot has 96 cells, the grid has 40".to_string())
+    );
+}
@@ -4,21 +4,8 @@ use num_complex::Complex;
 use num_traits::{Float, Num, NumCast};
 
 use std::ops::Range;
+use std::sync::Mutex;
 
-pub const NEAREST_NEIGHBORS: [[i32; 2]; 8] = [
-    [1, 0],
-    [1, 1],
-    [0, 1],
-    [-1, 1],
-    [-1, 0],
-    [-1, -1],
-    [0, -1],
-    [1, -1],
-];
-pub const N_NEAREST_NEIGHBORS: u8 = 8;
-
-pub const SECOND_ORDER_CENTRAL: [[f32; 3]; 3] =
-    [[0.0, 1.0, 0.0], [1.0, -4.0, 1.0], [0.0, 1.0, 0.0]];
 // pub const SECOND_ORDER_CENTRAL_5: [[f32; 5]; 5] = [
 //     [-1.0/12.0, 4.0/3.0, -5.0/2.0, 4.0/3.0, -1.0/12.0]
 // ]
@@ -30,10 +17,8 @@ pub type Color = [u8; 4];
 
 //width and height must be odd
 pub fn stencil_coords(width: GridInt, height: GridInt) -> impl Iterator<Item = GridPos> {
-    let get_range: fn(GridInt) -> Range<GridInt> = |n| (-n / 2..n / 2 + 1);
-    get_range(height)
-        .map(move |dy| get_range(width).map(move |dx| GridPos::new(dx, dy)))
-        .flatten()
+    let get_range: fn(GridInt) -> Range<GridInt> = |n| -n / 2..n / 2 + 1;
+    get_range(height).flat_map(move |dy| get_range(width).map(move |dx| GridPos::new(dx, dy)))
 }
 pub fn modulo<I: Num + Copy + std::cmp::PartialOrd>(lhs: I, rhs: I) -> I {
     let r = lhs % rhs;
@@ -43,11 +28,61 @@ pub fn modulo<I: Num + Copy + std::cmp::PartialOrd>(lhs: I, rhs: I) -> I {
     r
 }
 
+// Once seeded, every call to `generate_seed` draws from this generator instead
+// of the OS, so a whole run is reproducible from a single `u64`.
+static SEED_SOURCE: Mutex<Option<RandomGenerator>> = Mutex::new(None);
+
+/// Make all subsequent calls to `generate_seed` deterministic.
+pub fn set_seed(seed: u64) {
+    *SEED_SOURCE.lock().unwrap() = Some(RandomGenerator::seed(seed, 0));
+}
+
+/// The generator behind `generate_seed`, if one was seeded, e.g. to save in a snapshot.
+pub fn seed_source() -> Option<RandomGenerator> {
+    SEED_SOURCE.lock().unwrap().clone()
+}
+
+/// Restore a generator from `seed_source`.
+pub fn set_seed_source(source: Option<RandomGenerator>) {
+    *SEED_SOURCE.lock().unwrap() = source;
+}
+
+/// Generate a fresh seed from OS entropy, e.g. when none was configured. Only 63
+/// bits, since TOML integers are `i64`, and the seed should fit in `seed = ...`.
+pub fn entropy_seed() -> u64 {
+    let mut seed = [0_u8; 8];
+    getrandom::getrandom(&mut seed).expect("failed to getrandom");
+    u64::from_ne_bytes(seed) >> 1
+}
+
+pub fn next_u64(rng: &mut RandomGenerator) -> u64 {
+    (rng.next_u32() as u64) << 32 | rng.next_u32() as u64
+}
+
+/// Create an independent generator for the cell at `grid_pos`, seeded from `rng`.
+/// Each position gets its own PCG stream, so neighboring cells are uncorrelated.
+pub fn cell_rng(rng: &mut RandomGenerator, grid_pos: GridPos) -> RandomGenerator {
+    let stream = (grid_pos.y as u32 as u64) << 32 | grid_pos.x as u32 as u64;
+    RandomGenerator::seed(next_u64(rng), stream)
+}
+
+/// A sample of the standard normal distribution, by the Box-Muller transform.
+pub fn normal(rng: &mut RandomGenerator) -> f32 {
+    // In (0, 1], so the logarithm is finite
+    let radius = (-2.0 * randomize::f32_half_open_left(rng.next_u32()).ln()).sqrt();
+    let angle = std::f32::consts::TAU * randomize::f32_half_open_right(rng.next_u32());
+    radius * angle.cos()
+}
+
 /// Generate a pseudorandom seed for the game's PRNG.
 pub fn generate_seed() -> (u64, u64) {
     use byteorder::{ByteOrder, NativeEndian};
     use getrandom::getrandom;
 
+    if let Some(rng) = SEED_SOURCE.lock().unwrap().as_mut() {
+        return (next_u64(rng), next_u64(rng) | 1);
+    }
+
     let mut seed = [0_u8; 16];
 
     getrandom(&mut seed).expect("failed to getrandom");